@@ -0,0 +1,75 @@
+use crate::{seek_state, Diff, Reversible, SnapshotLog, State, Value};
+
+/// A materialized state at some index of a log.
+///
+/// Moving the cursor only applies and reverts the diffs on the path between its current and target
+/// index, which makes stepping through nearby states much cheaper than recovering each of them.
+#[derive(Clone, Debug)]
+pub struct Cursor<'a, V, D = Diff<V>> {
+    log: &'a SnapshotLog<V, D>,
+    state: State<V>,
+    index: usize,
+}
+
+impl<'a, V: Value, D: Reversible<V>> Cursor<'a, V, D> {
+    pub(crate) fn new(log: &'a SnapshotLog<V, D>, index: usize) -> Self {
+        Self {
+            state: log.recover(index),
+            log,
+            index,
+        }
+    }
+
+    /// The index of the state the cursor currently holds.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The state the cursor currently holds.
+    pub fn state(&self) -> &State<V> {
+        &self.state
+    }
+
+    /// Take the state the cursor currently holds.
+    pub fn into_state(self) -> State<V> {
+        self.state
+    }
+
+    /// Move the cursor to the state at `index`.
+    pub fn seek(&mut self, index: usize) {
+        assert!(
+            index <= self.log.len(),
+            "index {} is past the end of the log",
+            index
+        );
+
+        seek_state(
+            &self.log.log,
+            &mut self.state,
+            self.index,
+            index,
+            self.log.branching,
+        );
+        self.index = index;
+    }
+
+    /// Move the cursor to the next state, returning false if it is already at the end of the log.
+    pub fn step_forward(&mut self) -> bool {
+        if self.index < self.log.len() {
+            self.seek(self.index + 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move the cursor to the previous state, returning false if it is already at the base.
+    pub fn step_back(&mut self) -> bool {
+        if self.index > 0 {
+            self.seek(self.index - 1);
+            true
+        } else {
+            false
+        }
+    }
+}
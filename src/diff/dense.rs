@@ -0,0 +1,272 @@
+use std::mem::size_of;
+
+use crate::{Diff, DiffBackend, DiffEntries, Reversible, Value};
+
+/// A diff stored as a bitmap of the changed cells, with the original and new values of those cells
+/// packed in index order.
+///
+/// Words of the bitmap in which every cell changes are applied with a single slice copy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DenseDiff<V> {
+    // the index of the cell of the first bit, always a multiple of 64
+    start: usize,
+    present: Vec<u64>,
+    orig: Vec<V>,
+    new: Vec<V>,
+}
+
+impl<V: Value> DenseDiff<V> {
+    /// Build a dense diff from `(index, orig, new)` entries sorted by index.
+    ///
+    /// Panics if the entries are not sorted, or if a cell appears more than once.
+    pub fn from_sorted(entries: &[(usize, V, V)]) -> Self {
+        assert!(
+            entries.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "entries must be sorted by index, without duplicates"
+        );
+        let (start, end) = match (entries.first(), entries.last()) {
+            (Some(first), Some(last)) => (first.0 / 64 * 64, last.0 + 1),
+            _ => (0, 0),
+        };
+
+        let mut present = vec![0u64; (end - start).div_ceil(64)];
+        let mut orig = Vec::with_capacity(entries.len());
+        let mut new = Vec::with_capacity(entries.len());
+        for &(index, o, n) in entries {
+            present[(index - start) / 64] |= 1 << ((index - start) % 64);
+            orig.push(o);
+            new.push(n);
+        }
+
+        Self {
+            start,
+            present,
+            orig,
+            new,
+        }
+    }
+
+    /// The number of cells which change.
+    pub fn len(&self) -> usize {
+        self.new.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty()
+    }
+
+    /// The recorded cells as `(index, orig, new)`, sorted by index.
+    pub fn entries(&self) -> impl Iterator<Item = (usize, V, V)> + '_ {
+        self.indices()
+            .zip(self.orig.iter().zip(&self.new))
+            .map(|(index, (&orig, &new))| (index, orig, new))
+    }
+
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.present.iter().enumerate().flat_map(move |(w, &word)| {
+            let base = self.start + w * 64;
+            let mut bits = word;
+            std::iter::from_fn(move || {
+                (bits != 0).then(|| {
+                    let i = bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+                    base + i
+                })
+            })
+        })
+    }
+
+    /// The original and new value of the cell at `index`, if it changes.
+    pub fn get(&self, index: usize) -> Option<(V, V)> {
+        let offset = index.checked_sub(self.start)?;
+        let word = *self.present.get(offset / 64)?;
+        let bit = 1 << (offset % 64);
+        if word & bit == 0 {
+            return None;
+        }
+
+        // the rank of the cell among those which change is its position in the packed values
+        let rank = self.present[..offset / 64]
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum::<usize>()
+            + (word & (bit - 1)).count_ones() as usize;
+        Some((self.orig[rank], self.new[rank]))
+    }
+
+    /// Write `from` into the changed cells of the state, checking that they held `expected`.
+    fn write(&self, state: &mut [V], expected: &[V], from: &[V]) {
+        let mut k = 0;
+        for (w, &word) in self.present.iter().enumerate() {
+            let base = self.start + w * 64;
+            if word == u64::MAX {
+                // every cell in this word changes, so copy them over all at once
+                check!(state[base..base + 64] == expected[k..k + 64]);
+                state[base..base + 64].copy_from_slice(&from[k..k + 64]);
+                k += 64;
+            } else {
+                let mut bits = word;
+                while bits != 0 {
+                    let i = base + bits.trailing_zeros() as usize;
+                    check!(state[i] == expected[k]);
+                    state[i] = from[k];
+                    bits &= bits - 1;
+                    k += 1;
+                }
+            }
+        }
+    }
+
+    /// Apply the diff to the state.
+    ///
+    /// The original values are only verified in debug builds or with the `checks` feature.
+    pub fn apply(&self, state: &mut [V]) {
+        self.write(state, &self.orig, &self.new);
+    }
+
+    /// Revert the diff on the state, restoring the original values.
+    ///
+    /// The new values are only verified in debug builds or with the `checks` feature.
+    pub fn revert(&self, state: &mut [V]) {
+        self.write(state, &self.new, &self.orig);
+    }
+
+    /// The number of bytes needed to store the bitmap and the values.
+    pub fn storage_cost(&self) -> usize {
+        size_of::<usize>() + self.present.len() * size_of::<u64>() + 2 * self.len() * size_of::<V>()
+    }
+
+    fn heap_size(&self) -> usize {
+        self.present.capacity() * size_of::<u64>()
+            + (self.orig.capacity() + self.new.capacity()) * size_of::<V>()
+    }
+}
+
+/// A diff which is built up as a [`Diff`], but is stored as a [`DenseDiff`] once it is recorded in
+/// the log if that is smaller, i.e. if more than about one in 64 of the cells it spans change.
+///
+/// The high levels of the log, which span many appends, tend to touch most of the state and so
+/// are much smaller this way. Unions into a dense diff turn it back into a [`Diff`] first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdaptiveDiff<V> {
+    Sparse(Diff<V>),
+    Dense(DenseDiff<V>),
+}
+
+impl<V> Default for AdaptiveDiff<V> {
+    fn default() -> Self {
+        AdaptiveDiff::Sparse(Diff::new())
+    }
+}
+
+impl<V: Value> AdaptiveDiff<V> {
+    // the diff as a hash map, converting it if it is dense
+    fn sparse(&mut self) -> &mut Diff<V> {
+        if let AdaptiveDiff::Dense(dense) = self {
+            *self = AdaptiveDiff::Sparse(
+                dense
+                    .entries()
+                    .map(|(index, orig, new)| (index, (orig, new)))
+                    .collect(),
+            );
+        }
+        match self {
+            AdaptiveDiff::Sparse(sparse) => sparse,
+            AdaptiveDiff::Dense(_) => unreachable!(),
+        }
+    }
+}
+
+impl<V: Value> DiffBackend<V> for AdaptiveDiff<V> {
+    fn len(&self) -> usize {
+        match self {
+            AdaptiveDiff::Sparse(sparse) => sparse.len(),
+            AdaptiveDiff::Dense(dense) => dense.len(),
+        }
+    }
+
+    fn record(&mut self, index: usize, orig: V, new: V) {
+        self.sparse().insert(index, (orig, new));
+    }
+
+    fn retain_changes(&mut self) {
+        self.sparse().retain_changes();
+    }
+
+    fn apply(&self, state: &mut [V]) {
+        match self {
+            AdaptiveDiff::Sparse(sparse) => DiffBackend::apply(sparse, state),
+            AdaptiveDiff::Dense(dense) => dense.apply(state),
+        }
+    }
+
+    fn union(&mut self, src: &Self) {
+        match src {
+            AdaptiveDiff::Sparse(src) => self.sparse().union(src),
+            AdaptiveDiff::Dense(src) => {
+                let mut src = AdaptiveDiff::Dense(src.clone());
+                self.sparse().union(src.sparse());
+            }
+        }
+    }
+
+    fn compact(&mut self) {
+        let AdaptiveDiff::Sparse(sparse) = self else {
+            return;
+        };
+        let (Some(first), Some(last)) = (sparse.keys().min(), sparse.keys().max()) else {
+            return;
+        };
+        // the bitmap costs a word per 64 cells spanned plus one for its start, where the sparse
+        // form costs a word per cell
+        let words = (last - first / 64 * 64) / 64 + 2;
+        if sparse.len() > words {
+            let mut entries: Vec<_> = DiffEntries::entries(sparse).collect();
+            entries.sort_unstable_by_key(|&(index, _, _)| index);
+            *self = AdaptiveDiff::Dense(DenseDiff::from_sorted(&entries));
+        }
+    }
+
+    fn storage_cost(&self) -> usize {
+        match self {
+            AdaptiveDiff::Sparse(sparse) => DiffBackend::storage_cost(sparse),
+            AdaptiveDiff::Dense(dense) => dense.storage_cost(),
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        match self {
+            AdaptiveDiff::Sparse(sparse) => DiffBackend::heap_size(sparse),
+            AdaptiveDiff::Dense(dense) => dense.heap_size(),
+        }
+    }
+}
+
+impl<V: Value> Reversible<V> for AdaptiveDiff<V> {
+    fn revert(&self, state: &mut [V]) {
+        match self {
+            AdaptiveDiff::Sparse(sparse) => Reversible::revert(sparse, state),
+            AdaptiveDiff::Dense(dense) => dense.revert(state),
+        }
+    }
+}
+
+impl<V: Value> DiffEntries<V> for AdaptiveDiff<V> {
+    fn get(&self, index: usize) -> Option<(V, V)> {
+        match self {
+            AdaptiveDiff::Sparse(sparse) => sparse.get(&index).copied(),
+            AdaptiveDiff::Dense(dense) => dense.get(index),
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (usize, V, V)> + '_ {
+        let (sparse, dense) = match self {
+            AdaptiveDiff::Sparse(sparse) => (Some(DiffEntries::entries(sparse)), None),
+            AdaptiveDiff::Dense(dense) => (None, Some(dense.entries())),
+        };
+        sparse
+            .into_iter()
+            .flatten()
+            .chain(dense.into_iter().flatten())
+    }
+}
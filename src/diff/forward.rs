@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use crate::{DiffBackend, Value};
+
+/// A diff which stores only the new value of every changed cell.
+///
+/// This is the cheapest representation to store, but since the original values are gone, it can
+/// only be replayed forward: it does not implement [`crate::Reversible`], so a log of these cannot
+/// recover states backwards, truncate or hand out cursors, and it does not implement
+/// [`crate::DiffEntries`], so it cannot be verified with [`crate::SnapshotLog::try_append`] or
+/// written to disk. Use [`crate::SnapshotLog::recover_forward`] to recover states.
+///
+/// Without the original values, there is also no telling whether a write changed anything. Writes
+/// which leave a cell as it was are kept, including when a later diff puts a cell back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardDiff<V> {
+    new: HashMap<usize, V>,
+}
+
+impl<V> ForwardDiff<V> {
+    pub fn new() -> Self {
+        Self {
+            new: HashMap::new(),
+        }
+    }
+
+    /// Record that the cell at `index` is set to `new`, replacing any earlier record of that cell.
+    pub fn set(&mut self, index: usize, new: V) {
+        self.new.insert(index, new);
+    }
+
+    /// The recorded cells as `(index, new)`.
+    pub fn writes(&self) -> impl Iterator<Item = (usize, &V)> + '_ {
+        self.new.iter().map(|(&index, new)| (index, new))
+    }
+}
+
+impl<V> Default for ForwardDiff<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Value> DiffBackend<V> for ForwardDiff<V> {
+    fn len(&self) -> usize {
+        self.new.len()
+    }
+
+    fn record(&mut self, index: usize, _orig: V, new: V) {
+        // there's nowhere to keep the original value
+        self.set(index, new);
+    }
+
+    fn retain_changes(&mut self) {
+        // we can't tell which writes are no-ops, so keep all of them
+    }
+
+    fn apply(&self, state: &mut [V]) {
+        for (&i, &new) in &self.new {
+            state[i] = new;
+        }
+    }
+
+    fn union(&mut self, src: &Self) {
+        // the later write wins; a cell written back to its original value is still a write
+        self.new.extend(src.new.iter().map(|(&k, &new)| (k, new)));
+    }
+
+    fn storage_cost(&self) -> usize {
+        self.len() * (size_of::<usize>() + size_of::<V>())
+    }
+
+    fn heap_size(&self) -> usize {
+        // one control byte per bucket, on top of the entry itself
+        self.new.capacity() * (size_of::<(usize, V)>() + 1)
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::hash_map::Entry;
+use std::mem::size_of;
+
+use crate::{Diff, DiffBackend, DiffEntries, Reversible, Value};
+
+impl<V: Value> DiffBackend<V> for Diff<V> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn record(&mut self, index: usize, orig: V, new: V) {
+        self.insert(index, (orig, new));
+    }
+
+    fn retain_changes(&mut self) {
+        self.retain(|_, (orig, new)| orig != new);
+    }
+
+    fn apply(&self, state: &mut [V]) {
+        for (&i, &(orig, new)) in self {
+            check!(state[i] == orig);
+            state[i] = new;
+        }
+    }
+
+    fn union(&mut self, src: &Self) {
+        for (&k, &(expected, new)) in src.iter() {
+            match self.entry(k) {
+                Entry::Occupied(mut entry) => {
+                    let diff = entry.get_mut();
+                    let old = diff.0;
+                    check!(diff.1 == expected);
+
+                    // elide this diff, removing unnecessary
+                    if old == new {
+                        entry.remove();
+                    } else {
+                        diff.1 = new;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    // we haven't seen this index before; it pre-exists us, so add it here
+                    entry.insert((expected, new));
+                }
+            }
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        // one control byte per bucket, on top of the entry itself
+        self.capacity() * (size_of::<(usize, (V, V))>() + 1)
+    }
+}
+
+impl<V: Value> Reversible<V> for Diff<V> {
+    fn revert(&self, state: &mut [V]) {
+        for (&i, &(orig, new)) in self {
+            check!(state[i] == new);
+            state[i] = orig;
+        }
+    }
+}
+
+impl<V: Value> DiffEntries<V> for Diff<V> {
+    fn get(&self, index: usize) -> Option<(V, V)> {
+        self.get(&index).copied()
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (usize, V, V)> + '_ {
+        self.iter().map(|(&index, &(orig, new))| (index, orig, new))
+    }
+}
@@ -0,0 +1,189 @@
+//! The representations in which diffs may be stored.
+//!
+//! The default representation is [`crate::Diff`], a hash map from cell index to its original and
+//! new value. [`SortedDiff`] keeps the same entries in a vector sorted by cell index instead.
+//! [`AdaptiveDiff`] stores the diffs of the log which touch a large part of the state as a
+//! [`DenseDiff`], a bitmap of the changed cells with their values packed alongside. [`RangeDiff`]
+//! stores runs of consecutive cells, for workloads which write whole ranges at once. [`XorDiff`]
+//! stores only `orig ^ new` per cell, so it cannot be verified, but can still be reverted.
+//! [`ForwardDiff`] stores only the new value of every cell, so it can only be replayed forward.
+//!
+//! Every representation implements [`DiffBackend`], which is enough to append to a log and recover
+//! states forward from the base. Those which can be undone implement [`Reversible`], which is
+//! needed to recover states backwards and for cursors. Those which keep both values of every cell
+//! also implement [`DiffEntries`], which is needed to verify diffs and to write logs to disk.
+//!
+//! Diffs form an algebra: [`DiffBackend::compose`] chains two diffs which follow each other,
+//! [`squash`] chains any number of them, and [`DiffEntries::invert`] undoes a diff. Composition is
+//! associative and the empty diff is its identity, and a diff composed with its inverse is empty.
+
+use std::mem::size_of;
+
+use crate::{DiffError, Value};
+
+mod dense;
+mod forward;
+mod map;
+mod range;
+mod sorted;
+mod xor;
+
+pub use dense::{AdaptiveDiff, DenseDiff};
+pub use forward::ForwardDiff;
+pub use range::RangeDiff;
+pub use sorted::SortedDiff;
+pub use xor::XorDiff;
+
+/// A representation of the difference between two states: the cells which changed, and how.
+pub trait DiffBackend<V: Value>: Clone + Default {
+    /// The number of cells recorded in the diff.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record that the cell at `index` changes from `orig` to `new`, replacing any earlier record
+    /// of that cell.
+    fn record(&mut self, index: usize, orig: V, new: V);
+
+    /// Drop the records of cells whose value does not actually change.
+    fn retain_changes(&mut self);
+
+    /// Apply the diff to the state.
+    ///
+    /// The original values are only verified in debug builds or with the `checks` feature.
+    fn apply(&self, state: &mut [V]);
+
+    /// Union the later diff `src` into this one, so that this diff leads straight to the state
+    /// that `src` leads to. Cells which end up with their original value are dropped.
+    fn union(&mut self, src: &Self);
+
+    /// The diff which leads straight to the state that `next` leads to, where `next` starts from
+    /// the state this diff leads to.
+    fn compose(&self, next: &Self) -> Self {
+        let mut composed = self.clone();
+        composed.union(next);
+        composed
+    }
+
+    /// Called once the diff is stored as an entry of the log, after which it is mostly read.
+    /// Representations may use this to switch to a more compact form.
+    fn compact(&mut self) {}
+
+    /// The number of bytes needed to store the recorded cells.
+    fn storage_cost(&self) -> usize {
+        self.len() * (size_of::<usize>() + 2 * size_of::<V>())
+    }
+
+    /// The number of bytes of heap memory held by the representation.
+    fn heap_size(&self) -> usize;
+}
+
+/// A diff which can be undone, so that states can be recovered backwards from a later one.
+pub trait Reversible<V: Value>: DiffBackend<V> {
+    /// Revert the diff on the state, restoring the original values.
+    ///
+    /// The new values are only verified in debug builds or with the `checks` feature.
+    fn revert(&self, state: &mut [V]);
+}
+
+/// A diff which records the original and new value of every cell, so that it can be verified
+/// against a state and written out entry by entry.
+pub trait DiffEntries<V: Value>: Reversible<V> {
+    /// The original and new value of the cell at `index`, if the diff records it.
+    fn get(&self, index: usize) -> Option<(V, V)>;
+
+    /// The recorded cells as `(index, orig, new)`.
+    fn entries(&self) -> impl Iterator<Item = (usize, V, V)> + '_;
+
+    /// Apply the diff to the state, verifying every cell before any is modified.
+    fn try_apply(&self, state: &mut [V]) -> Result<(), DiffError<V>> {
+        for (index, expected, _) in self.entries() {
+            match state.get(index) {
+                None => {
+                    return Err(DiffError::OutOfBounds {
+                        index,
+                        len: state.len(),
+                    })
+                }
+                Some(&found) if found != expected => {
+                    return Err(DiffError::Mismatch {
+                        index,
+                        expected,
+                        found,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        self.apply(state);
+        Ok(())
+    }
+
+    /// Like [`DiffEntries::try_apply`], but reports every cell which does not match rather than
+    /// the first one, in no particular order. On error, the state is not modified.
+    fn apply_checked(&self, state: &mut [V]) -> Result<(), Vec<DiffError<V>>> {
+        let conflicts: Vec<_> = self
+            .entries()
+            .filter_map(|(index, expected, _)| match state.get(index) {
+                None => Some(DiffError::OutOfBounds {
+                    index,
+                    len: state.len(),
+                }),
+                Some(&found) if found != expected => Some(DiffError::Mismatch {
+                    index,
+                    expected,
+                    found,
+                }),
+                Some(_) => None,
+            })
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+
+        self.apply(state);
+        Ok(())
+    }
+
+    /// The diff which undoes this one.
+    fn invert(&self) -> Self {
+        let mut inverse = Self::default();
+        for (index, orig, new) in self.entries() {
+            inverse.record(index, new, orig);
+        }
+        inverse
+    }
+
+    /// Like [`DiffBackend::union`], but verifies that `src` picks up where this diff left off.
+    ///
+    /// On error, this diff is not modified.
+    fn try_union(&mut self, src: &Self) -> Result<(), DiffError<V>> {
+        for (index, expected, _) in src.entries() {
+            if let Some((_, found)) = self.get(index) {
+                if found != expected {
+                    return Err(DiffError::Mismatch {
+                        index,
+                        expected,
+                        found,
+                    });
+                }
+            }
+        }
+
+        self.union(src);
+        Ok(())
+    }
+}
+
+/// Compose diffs which each follow the previous one into a single diff, which is empty if there
+/// are none.
+pub fn squash<V: Value, D: DiffBackend<V>>(diffs: &[D]) -> D {
+    let mut squashed = D::default();
+    for diff in diffs {
+        squashed.union(diff);
+    }
+    squashed
+}
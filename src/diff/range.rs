@@ -0,0 +1,217 @@
+use std::mem::size_of;
+
+use crate::{DiffBackend, DiffEntries, Reversible, Value};
+
+/// A diff stored as runs of consecutive cells, each with a slice of original and new values.
+///
+/// Writes to contiguous ranges, such as copying into a buffer, are stored as a single run rather
+/// than an entry per cell, and each run is applied with a single slice copy. Runs never overlap or
+/// touch, so a range written in several pieces is still stored as one run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeDiff<V> {
+    // sorted by start
+    runs: Vec<Run<V>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Run<V> {
+    start: usize,
+    orig: Vec<V>,
+    new: Vec<V>,
+}
+
+impl<V> Run<V> {
+    fn end(&self) -> usize {
+        self.start + self.new.len()
+    }
+}
+
+impl<V> RangeDiff<V> {
+    pub fn new() -> Self {
+        Self { runs: Vec::new() }
+    }
+
+    /// The runs of the diff as `(start, orig, new)`, sorted by start.
+    pub fn runs(&self) -> impl Iterator<Item = (usize, &[V], &[V])> + '_ {
+        self.runs
+            .iter()
+            .map(|run| (run.start, run.orig.as_slice(), run.new.as_slice()))
+    }
+}
+
+impl<V> Default for RangeDiff<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Value> RangeDiff<V> {
+    /// Record that the cells starting at `start` change from `orig` to `new`, replacing any
+    /// earlier record of those cells.
+    pub fn record_range(&mut self, start: usize, orig: &[V], new: &[V]) {
+        assert_eq!(
+            orig.len(),
+            new.len(),
+            "orig and new must be the same length"
+        );
+        self.splice(start, orig, new, false);
+    }
+
+    // write a run over the diff, either replacing the cells it covers or composing with them
+    fn splice(&mut self, start: usize, orig: &[V], new: &[V], compose: bool) {
+        if new.is_empty() {
+            return;
+        }
+        let end = start + new.len();
+
+        // every run which overlaps or touches the new one is merged into it
+        let lo = self.runs.partition_point(|run| run.end() < start);
+        let hi = lo + self.runs[lo..].partition_point(|run| run.start <= end);
+        let (merged_start, merged_end) = if lo < hi {
+            (
+                self.runs[lo].start.min(start),
+                self.runs[hi - 1].end().max(end),
+            )
+        } else {
+            (start, end)
+        };
+
+        let mut merged = Run {
+            start: merged_start,
+            orig: Vec::with_capacity(merged_end - merged_start),
+            new: Vec::with_capacity(merged_end - merged_start),
+        };
+        // the runs we merge are only ever separated by parts of the new run, so every cell of the
+        // merged run is covered by one or the other
+        merged.orig.resize(merged_end - merged_start, orig[0]);
+        merged.new.resize(merged_end - merged_start, new[0]);
+        merged.orig[start - merged_start..end - merged_start].copy_from_slice(orig);
+        merged.new[start - merged_start..end - merged_start].copy_from_slice(new);
+
+        for run in self.runs.drain(lo..hi) {
+            let offset = run.start - merged_start;
+            // the part of the old run which the new one covers
+            let (from, to) = (
+                start.clamp(run.start, run.end()) - run.start,
+                end.clamp(run.start, run.end()) - run.start,
+            );
+
+            merged.orig[offset..offset + from].copy_from_slice(&run.orig[..from]);
+            merged.new[offset..offset + from].copy_from_slice(&run.new[..from]);
+            if compose {
+                // the cells keep their original value, but now lead to the new one
+                check!(run.new[from..to] == merged.orig[offset + from..offset + to]);
+                merged.orig[offset + from..offset + to].copy_from_slice(&run.orig[from..to]);
+            }
+            merged.orig[offset + to..offset + run.new.len()].copy_from_slice(&run.orig[to..]);
+            merged.new[offset + to..offset + run.new.len()].copy_from_slice(&run.new[to..]);
+        }
+
+        if compose {
+            // elide the cells which end up where they started, splitting the run around them
+            let runs = split_changes(merged);
+            self.runs.splice(lo..lo, runs);
+        } else {
+            self.runs.insert(lo, merged);
+        }
+    }
+}
+
+// split a run into the runs of cells which actually change
+fn split_changes<V: Value>(run: Run<V>) -> Vec<Run<V>> {
+    if run.orig.iter().zip(&run.new).all(|(orig, new)| orig != new) {
+        return vec![run];
+    }
+
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < run.new.len() {
+        if run.orig[i] == run.new[i] {
+            i += 1;
+            continue;
+        }
+        let len = run.orig[i..]
+            .iter()
+            .zip(&run.new[i..])
+            .take_while(|(orig, new)| orig != new)
+            .count();
+        runs.push(Run {
+            start: run.start + i,
+            orig: run.orig[i..i + len].to_vec(),
+            new: run.new[i..i + len].to_vec(),
+        });
+        i += len;
+    }
+    runs
+}
+
+impl<V: Value> DiffBackend<V> for RangeDiff<V> {
+    fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.new.len()).sum()
+    }
+
+    fn record(&mut self, index: usize, orig: V, new: V) {
+        self.splice(index, &[orig], &[new], false);
+    }
+
+    fn retain_changes(&mut self) {
+        self.runs = std::mem::take(&mut self.runs)
+            .into_iter()
+            .flat_map(split_changes)
+            .collect();
+    }
+
+    fn apply(&self, state: &mut [V]) {
+        for run in &self.runs {
+            let cells = &mut state[run.start..run.end()];
+            check!(cells == run.orig.as_slice());
+            cells.copy_from_slice(&run.new);
+        }
+    }
+
+    fn union(&mut self, src: &Self) {
+        for run in &src.runs {
+            self.splice(run.start, &run.orig, &run.new, true);
+        }
+    }
+
+    fn storage_cost(&self) -> usize {
+        // the start and length of each run, then its values
+        self.runs.len() * 2 * size_of::<usize>() + self.len() * 2 * size_of::<V>()
+    }
+
+    fn heap_size(&self) -> usize {
+        self.runs.capacity() * size_of::<Run<V>>()
+            + self
+                .runs
+                .iter()
+                .map(|run| (run.orig.capacity() + run.new.capacity()) * size_of::<V>())
+                .sum::<usize>()
+    }
+}
+
+impl<V: Value> Reversible<V> for RangeDiff<V> {
+    fn revert(&self, state: &mut [V]) {
+        for run in &self.runs {
+            let cells = &mut state[run.start..run.end()];
+            check!(cells == run.new.as_slice());
+            cells.copy_from_slice(&run.orig);
+        }
+    }
+}
+
+impl<V: Value> DiffEntries<V> for RangeDiff<V> {
+    fn get(&self, index: usize) -> Option<(V, V)> {
+        let pos = self.runs.partition_point(|run| run.end() <= index);
+        let run = self.runs.get(pos).filter(|run| run.start <= index)?;
+        Some((run.orig[index - run.start], run.new[index - run.start]))
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (usize, V, V)> + '_ {
+        self.runs.iter().flat_map(|run| {
+            (run.start..)
+                .zip(run.orig.iter().zip(&run.new))
+                .map(|(index, (&orig, &new))| (index, orig, new))
+        })
+    }
+}
@@ -0,0 +1,143 @@
+use std::mem::size_of;
+
+use crate::{DiffBackend, DiffEntries, Reversible, Value};
+
+/// A diff stored as `(index, orig, new)` entries in a vector sorted by index.
+///
+/// Compared to [`crate::Diff`], entries are stored without hashing or per-bucket overhead, unions
+/// are merges of two sorted runs, and iteration (and so serialization) is deterministic. Applying
+/// a diff is faster, but inserting new cells into the large diffs which accumulate in the cache
+/// costs time linear in their size, so appends are slower.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SortedDiff<V> {
+    entries: Vec<(usize, V, V)>,
+}
+
+impl<V> SortedDiff<V> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// The recorded cells, sorted by index.
+    pub fn as_slice(&self) -> &[(usize, V, V)] {
+        &self.entries
+    }
+
+    // the position of the entry for `index`, or where it would be inserted
+    fn search(&self, index: usize) -> Result<usize, usize> {
+        self.entries.binary_search_by_key(&index, |&(i, _, _)| i)
+    }
+}
+
+impl<V> Default for SortedDiff<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Value> FromIterator<(usize, V, V)> for SortedDiff<V> {
+    fn from_iter<T: IntoIterator<Item = (usize, V, V)>>(iter: T) -> Self {
+        let mut entries: Vec<_> = iter.into_iter().collect();
+        // keep the last record of each cell, like repeated calls to record would
+        entries.reverse();
+        entries.sort_by_key(|&(i, _, _)| i);
+        entries.dedup_by_key(|&mut (i, _, _)| i);
+        Self { entries }
+    }
+}
+
+impl<V: Value> DiffBackend<V> for SortedDiff<V> {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn record(&mut self, index: usize, orig: V, new: V) {
+        match self.search(index) {
+            Ok(pos) => self.entries[pos] = (index, orig, new),
+            Err(pos) => self.entries.insert(pos, (index, orig, new)),
+        }
+    }
+
+    fn retain_changes(&mut self) {
+        self.entries.retain(|&(_, orig, new)| orig != new);
+    }
+
+    fn apply(&self, state: &mut [V]) {
+        for &(i, orig, new) in &self.entries {
+            check!(state[i] == orig);
+            state[i] = new;
+        }
+    }
+
+    fn union(&mut self, src: &Self) {
+        // the destination is usually much larger than the source (it accumulates many appends),
+        // so first update the cells we already have in place, as that needs no merge at all
+        let mut inserts = Vec::new();
+        let mut elided = false;
+        let mut start = 0;
+        for &(index, expected, new) in &src.entries {
+            // src is sorted too, so we never need to look behind the last match
+            let pos = start + self.entries[start..].partition_point(|&(i, _, _)| i < index);
+            start = pos;
+            match self.entries.get_mut(pos) {
+                Some(entry) if entry.0 == index => {
+                    check!(entry.2 == expected);
+                    entry.2 = new;
+                    // elide this diff, removing unnecessary
+                    elided |= entry.1 == new;
+                }
+                _ => inserts.push((index, expected, new)),
+            }
+        }
+
+        if elided {
+            self.entries.retain(|&(_, orig, new)| orig != new);
+        }
+        if inserts.is_empty() {
+            return;
+        }
+
+        // merge the cells we haven't seen before in from the back, so that every entry moves once
+        let mut read = self.entries.len();
+        self.entries.resize(read + inserts.len(), inserts[0]);
+        let mut write = self.entries.len();
+        while let Some(&insert) = inserts.last() {
+            write -= 1;
+            if read > 0 && self.entries[read - 1].0 > insert.0 {
+                read -= 1;
+                self.entries[write] = self.entries[read];
+            } else {
+                self.entries[write] = insert;
+                inserts.pop();
+            }
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.entries.capacity() * size_of::<(usize, V, V)>()
+    }
+}
+
+impl<V: Value> Reversible<V> for SortedDiff<V> {
+    fn revert(&self, state: &mut [V]) {
+        for &(i, orig, new) in &self.entries {
+            check!(state[i] == new);
+            state[i] = orig;
+        }
+    }
+}
+
+impl<V: Value> DiffEntries<V> for SortedDiff<V> {
+    fn get(&self, index: usize) -> Option<(V, V)> {
+        self.search(index).ok().map(|pos| {
+            let (_, orig, new) = self.entries[pos];
+            (orig, new)
+        })
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (usize, V, V)> + '_ {
+        self.entries.iter().copied()
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ops::BitXor;
+
+use crate::{DiffBackend, Reversible, Value};
+
+/// A diff which stores `orig ^ new` for every changed cell rather than both values.
+///
+/// Applying and reverting the diff are the same operation, so it stays reversible while storing a
+/// single value per cell. In exchange, the original values are lost, so the diff cannot be checked
+/// against a state and does not implement [`crate::DiffEntries`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XorDiff<V> {
+    deltas: HashMap<usize, V>,
+}
+
+impl<V> XorDiff<V> {
+    pub fn new() -> Self {
+        Self {
+            deltas: HashMap::new(),
+        }
+    }
+
+    /// The recorded cells as `(index, orig ^ new)`.
+    pub fn deltas(&self) -> impl Iterator<Item = (usize, &V)> + '_ {
+        self.deltas.iter().map(|(&index, delta)| (index, delta))
+    }
+}
+
+impl<V> Default for XorDiff<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Value + Default + BitXor<Output = V>> XorDiff<V> {
+    fn xor(&self, state: &mut [V]) {
+        for (&i, &delta) in &self.deltas {
+            state[i] = state[i] ^ delta;
+        }
+    }
+}
+
+impl<V: Value + Default + BitXor<Output = V>> DiffBackend<V> for XorDiff<V> {
+    fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    fn record(&mut self, index: usize, orig: V, new: V) {
+        self.deltas.insert(index, orig ^ new);
+    }
+
+    fn retain_changes(&mut self) {
+        self.deltas.retain(|_, &mut delta| delta != V::default());
+    }
+
+    fn apply(&self, state: &mut [V]) {
+        self.xor(state);
+    }
+
+    fn union(&mut self, src: &Self) {
+        for (&k, &delta) in &src.deltas {
+            match self.deltas.entry(k) {
+                Entry::Occupied(mut entry) => {
+                    let composed = *entry.get() ^ delta;
+                    // the deltas cancel out, so the cell is back to its original value
+                    if composed == V::default() {
+                        entry.remove();
+                    } else {
+                        entry.insert(composed);
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(delta);
+                }
+            }
+        }
+    }
+
+    fn storage_cost(&self) -> usize {
+        self.len() * (size_of::<usize>() + size_of::<V>())
+    }
+
+    fn heap_size(&self) -> usize {
+        // one control byte per bucket, on top of the entry itself
+        self.deltas.capacity() * (size_of::<(usize, V)>() + 1)
+    }
+}
+
+impl<V: Value + Default + BitXor<Output = V>> Reversible<V> for XorDiff<V> {
+    fn revert(&self, state: &mut [V]) {
+        self.xor(state);
+    }
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+/// An inconsistency between a diff and the state (or diff) it was applied to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffError<V> {
+    /// The cell at `index` did not hold the value the diff expected to replace.
+    Mismatch { index: usize, expected: V, found: V },
+    /// The diff refers to a cell past the end of a state of `len` cells.
+    OutOfBounds { index: usize, len: usize },
+}
+
+impl<V: Debug> Display for DiffError<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffError::Mismatch {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "cell {} was expected to hold {:?}, but held {:?}",
+                index, expected, found
+            ),
+            DiffError::OutOfBounds { index, len } => write!(
+                f,
+                "cell {} is out of range for a state of {} cells",
+                index, len
+            ),
+        }
+    }
+}
+
+impl<V: Debug> Error for DiffError<V> {}
+
+/// A failure to read a snapshot log file.
+#[derive(Debug)]
+pub enum FormatError {
+    /// The underlying reader failed.
+    Io(std::io::Error),
+    /// The file ended before the record it was reading was complete.
+    Truncated,
+    /// The file does not start with the magic number of a snapshot log.
+    BadMagic([u8; 8]),
+    /// The file was written in a format version this build cannot read.
+    UnsupportedVersion(u32),
+    /// The file was written with values of a different size than the ones requested.
+    ValueSize { expected: usize, found: usize },
+    /// The file is well-formed, but its contents are inconsistent.
+    Corrupt(&'static str),
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Io(e) => write!(f, "failed to read snapshot log: {}", e),
+            FormatError::Truncated => write!(f, "snapshot log file is truncated"),
+            FormatError::BadMagic(magic) => {
+                write!(f, "not a snapshot log file (magic number {:02x?})", magic)
+            }
+            FormatError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot log format version {}", version)
+            }
+            FormatError::ValueSize { expected, found } => write!(
+                f,
+                "snapshot log holds values of {} bytes, but {} were expected",
+                found, expected
+            ),
+            FormatError::Corrupt(reason) => write!(f, "snapshot log file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl Error for FormatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FormatError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FormatError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            FormatError::Truncated
+        } else {
+            FormatError::Io(e)
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! The on-disk format of a snapshot log.
+//!
+//! All integers are little-endian. A file consists of:
+//!
+//! - a header: the magic number `RSNAPLOG`, the format version (u32), the size of an encoded
+//!   value (u32), the branching factor of the log (u32), and the number of cells in a state, diffs
+//!   in the log, diffs in the cache and original indices (each u64);
+//! - the base state, as one encoded value per cell;
+//! - the original indices of the states at the start of the log which were renumbered when it was
+//!   pruned or rebased (each u64; see [`SnapshotLog::origin`]);
+//! - one record per diff of the log, followed by one per diff of the cache. Each record is its
+//!   number of entries (u64) followed by that many `(index: u64, orig, new)` entries.
+
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::mem::size_of;
+
+use crate::{DiffEntries, FormatError, SnapshotLog, State, Value};
+
+/// The magic number at the start of every snapshot log file.
+pub const MAGIC: [u8; 8] = *b"RSNAPLOG";
+/// The version of the format written by this build.
+pub const VERSION: u32 = 3;
+
+/// Values with a fixed-width little-endian encoding, which may be written to a file.
+pub trait Encode: Sized {
+    /// The number of bytes in the encoding of every value.
+    const SIZE: usize;
+
+    /// Encode the value into `buf`, which is exactly `SIZE` bytes long.
+    fn encode(&self, buf: &mut [u8]);
+
+    /// Decode a value from `buf`, which is exactly `SIZE` bytes long.
+    fn decode(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_encode {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                const SIZE: usize = size_of::<$t>();
+
+                fn encode(&self, buf: &mut [u8]) {
+                    buf.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(buf: &[u8]) -> Self {
+                    Self::from_le_bytes(buf.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_encode!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl<V: Value + Encode, D: DiffEntries<V>> SnapshotLog<V, D> {
+    /// Write the log, including its base state and cache, to `writer`.
+    pub fn save<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+
+        write_preamble::<_, V>(&mut writer, &MAGIC, self.branching)?;
+        let lens = [
+            self.base.len(),
+            self.log.len(),
+            self.cache.len(),
+            self.origins.len(),
+        ];
+        for len in lens {
+            writer.write_all(&(len as u64).to_le_bytes())?;
+        }
+
+        let mut buf = vec![0; V::SIZE];
+        write_state(&mut writer, &self.base, &mut buf)?;
+        for &origin in &self.origins {
+            writer.write_all(&(origin as u64).to_le_bytes())?;
+        }
+
+        for diff in self.log.iter().chain(&self.cache) {
+            write_diff(&mut writer, diff, &mut buf)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Read a log previously written by [`SnapshotLog::save`] from `reader`.
+    pub fn load<R: Read>(reader: R) -> Result<Self, FormatError> {
+        let mut reader = BufReader::new(reader);
+
+        let branching = read_preamble::<_, V>(&mut reader, &MAGIC)?;
+        let state_len = read_len(&mut reader)?;
+        let log_len = read_len(&mut reader)?;
+        let cache_len = read_len(&mut reader)?;
+        let origins_len = read_len(&mut reader)?;
+        if origins_len > log_len.saturating_add(1) {
+            return Err(FormatError::Corrupt("more original indices than states"));
+        }
+
+        let mut buf = vec![0; V::SIZE];
+        let base = read_state(&mut reader, state_len, &mut buf)?;
+        let origins = (0..origins_len)
+            .map(|_| read_len(&mut reader))
+            .collect::<Result<Vec<usize>, _>>()?;
+        if origins.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(FormatError::Corrupt(
+                "the original indices are out of order",
+            ));
+        }
+
+        let log = (0..log_len)
+            .map(|_| read_diff(&mut reader, state_len, &mut buf))
+            .collect::<Result<Vec<D>, _>>()?;
+        let cache = (0..cache_len)
+            .map(|_| read_diff(&mut reader, state_len, &mut buf))
+            .collect::<Result<Vec<D>, _>>()?;
+
+        if reader.read(&mut [0])? != 0 {
+            return Err(FormatError::Corrupt("trailing data after the last record"));
+        }
+
+        // the diffs are only as good as the file, so verify them rather than trusting them
+        let mut loaded = SnapshotLog::try_from_parts(base, log, branching)
+            .map_err(|_| FormatError::Corrupt("a diff does not match the state it applies to"))?;
+        if cache.len() != loaded.cache.len()
+            || !cache.iter().zip(&loaded.cache).all(|(stored, rebuilt)| {
+                stored.len() == rebuilt.len()
+                    && stored
+                        .entries()
+                        .all(|(index, orig, new)| rebuilt.get(index) == Some((orig, new)))
+            })
+        {
+            return Err(FormatError::Corrupt("the cache does not match the log"));
+        }
+        loaded.origins = origins;
+        Ok(loaded)
+    }
+}
+
+// write the magic number, format version and value size which start every file
+pub(crate) fn write_preamble<W: Write, V: Encode>(
+    writer: &mut W,
+    magic: &[u8; 8],
+    branching: usize,
+) -> std::io::Result<()> {
+    writer.write_all(magic)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(V::SIZE as u32).to_le_bytes())?;
+    writer.write_all(&(branching as u32).to_le_bytes())
+}
+
+// check that the file starts with the given magic number and can be read with these values, and
+// return the branching factor of the log
+pub(crate) fn read_preamble<R: Read, V: Encode>(
+    reader: &mut R,
+    magic: &[u8; 8],
+) -> Result<usize, FormatError> {
+    let mut found = [0; 8];
+    reader.read_exact(&mut found)?;
+    if &found != magic {
+        return Err(FormatError::BadMagic(found));
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    let value_size = read_u32(reader)? as usize;
+    if value_size != V::SIZE {
+        return Err(FormatError::ValueSize {
+            expected: V::SIZE,
+            found: value_size,
+        });
+    }
+    match read_u32(reader)? as usize {
+        branching @ 2.. => Ok(branching),
+        _ => Err(FormatError::Corrupt("the branching factor is less than 2")),
+    }
+}
+
+pub(crate) fn write_state<W: Write, V: Encode>(
+    writer: &mut W,
+    state: &[V],
+    buf: &mut [u8],
+) -> std::io::Result<()> {
+    for value in state {
+        value.encode(buf);
+        writer.write_all(buf)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_state<R: Read, V: Encode>(
+    reader: &mut R,
+    len: usize,
+    buf: &mut [u8],
+) -> Result<State<V>, FormatError> {
+    let mut state = State::new();
+    for _ in 0..len {
+        reader.read_exact(buf)?;
+        state.push(V::decode(buf));
+    }
+    Ok(state)
+}
+
+pub(crate) fn write_diff<W: Write, V: Value + Encode, D: DiffEntries<V>>(
+    writer: &mut W,
+    diff: &D,
+    buf: &mut [u8],
+) -> std::io::Result<()> {
+    writer.write_all(&(diff.len() as u64).to_le_bytes())?;
+    for (index, orig, new) in diff.entries() {
+        writer.write_all(&(index as u64).to_le_bytes())?;
+        orig.encode(buf);
+        writer.write_all(buf)?;
+        new.encode(buf);
+        writer.write_all(buf)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_diff<R: Read, V: Value + Encode, D: DiffEntries<V>>(
+    reader: &mut R,
+    state_len: usize,
+    buf: &mut [u8],
+) -> Result<D, FormatError> {
+    let len = read_len(reader)?;
+    let mut diff = D::default();
+    for _ in 0..len {
+        let index = read_len(reader)?;
+        if index >= state_len {
+            return Err(FormatError::Corrupt(
+                "diff refers to a cell outside the state",
+            ));
+        }
+        reader.read_exact(buf)?;
+        let orig = V::decode(buf);
+        reader.read_exact(buf)?;
+        let new = V::decode(buf);
+        if diff.get(index).is_some() {
+            return Err(FormatError::Corrupt("diff refers to the same cell twice"));
+        }
+        diff.record(index, orig, new);
+    }
+    Ok(diff)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, FormatError> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_len<R: Read>(reader: &mut R) -> Result<usize, FormatError> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    usize::try_from(u64::from_le_bytes(buf))
+        .map_err(|_| FormatError::Corrupt("length does not fit in memory"))
+}
+
+// crc-32 (ieee), as used by zip and png
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    0xedb8_8320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !bytes.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
@@ -0,0 +1,279 @@
+//! Histories which fork from a shared past.
+//!
+//! Entry j of a log only depends on the states between its parent and j, so a branch which forks
+//! from another at index k can use the entries up to k of the branch it forked from as they are.
+//! Each branch only stores the entries it appended itself, and looks up older ones in its
+//! ancestors.
+//!
+//! Two branches can be reconciled with a three-way [`History::merge`] from a state they share.
+
+use crate::{
+    cache_len, clear_low, common_ancestor, descent, digits, net_diff, next_entry, Diff,
+    DiffBackend, DiffCache, DiffEntries, SnapshotLog, State, StateLog, Value,
+};
+
+/// Identifies a branch of a [`History`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BranchId(usize);
+
+/// A tree of logs which share every entry before the points at which they forked.
+#[derive(Clone, Debug)]
+pub struct History<V, D = Diff<V>> {
+    base: State<V>,
+    branching: usize,
+    branches: Vec<Branch<V, D>>,
+}
+
+/// A cell which two branches both changed since the state they are merged from, to different
+/// values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Conflict<V> {
+    /// The index of the cell.
+    pub index: usize,
+    /// The value of the cell in the state both branches started from.
+    pub base: V,
+    /// The value of the cell at the head of the branch which is merged into.
+    pub ours: V,
+    /// The value of the cell at the head of the branch which is merged.
+    pub theirs: V,
+}
+
+/// The outcome of a [`History::merge`].
+#[derive(Clone, Debug)]
+pub struct Merge<V, D> {
+    /// The diff which brings the head of our branch to the merged state. Conflicts which weren't
+    /// resolved are left as they are on our side.
+    pub diff: D,
+    /// The conflicts which the resolver left unresolved, by cell.
+    pub conflicts: Vec<Conflict<V>>,
+}
+
+#[derive(Clone, Debug)]
+struct Branch<V, D> {
+    // the branch this one forked from and the index it forked at, unless it is the root
+    parent: Option<(BranchId, usize)>,
+    // the entries after the fork point
+    log: StateLog<D>,
+    cache: DiffCache<D>,
+    state: State<V>,
+}
+
+impl<V: Value, D: DiffBackend<V>> History<V, D> {
+    /// The branch every history starts with.
+    pub const ROOT: BranchId = BranchId(0);
+
+    /// Create a history with only a root branch which starts from the provided base state.
+    pub fn from_base(base: State<V>) -> Self {
+        Self::with_branching(base, 2)
+    }
+
+    /// Like [`History::from_base`], with the given branching factor (at least 2).
+    pub fn with_branching(base: State<V>, branching: usize) -> Self {
+        Self::from_log(SnapshotLog::with_branching(base, branching))
+    }
+
+    /// Create a history whose root branch is the given log. Its checkpoints are not kept.
+    pub fn from_log(log: SnapshotLog<V, D>) -> Self {
+        Self {
+            base: log.base,
+            branching: log.branching,
+            branches: vec![Branch {
+                parent: None,
+                log: log.log,
+                cache: log.cache,
+                state: log.state,
+            }],
+        }
+    }
+
+    /// Create a branch whose history is the one of `branch` up to `index`, and which continues
+    /// from the state at `index`. The entries up to `index` are shared, not copied.
+    pub fn fork(&mut self, branch: BranchId, index: usize) -> BranchId {
+        assert!(
+            index <= self.len(branch),
+            "index {} is past the end of the branch",
+            index
+        );
+        let k = self.branching;
+
+        // the same cache a log which only appended the shared entries would hold
+        let depth = digits(index, k);
+        let cache = (0..cache_len(index, k))
+            .map(|level| {
+                let ancestor = clear_low(index, depth - level as u32, k);
+                self.compose(branch, ancestor, index)
+            })
+            .collect();
+
+        self.branches.push(Branch {
+            parent: Some((branch, index)),
+            log: StateLog::new(),
+            cache,
+            state: self.recover(branch, index),
+        });
+        BranchId(self.branches.len() - 1)
+    }
+
+    /// Apply the diff to the current state of the branch and record it as its next entry.
+    pub fn append(&mut self, branch: BranchId, diff: D) {
+        let len = self.len(branch);
+        let k = self.branching;
+        diff.apply(&mut self.branches[branch.0].state);
+
+        // the cache is taken out while the entries are looked up, which may be in any branch
+        let mut cache = std::mem::take(&mut self.branches[branch.0].cache);
+        let entry = next_entry(&mut cache, diff, len, k, |ancestor| {
+            self.compose(branch, ancestor, len)
+        });
+
+        let branch = &mut self.branches[branch.0];
+        branch.cache = cache;
+        branch.log.push(entry);
+    }
+
+    /// Recover the state at `index` of the branch by applying diffs onto the base state.
+    pub fn recover(&self, branch: BranchId, index: usize) -> State<V> {
+        assert!(
+            index <= self.len(branch),
+            "index {} is past the end of the branch",
+            index
+        );
+        let mut state = self.base.clone();
+        for index in descent(0, index, self.branching) {
+            self.entry(branch, index).apply(&mut state);
+        }
+        state
+    }
+
+    // the net diff of the branch from the state at `ancestor` to the state at `to`
+    fn compose(&self, branch: BranchId, ancestor: usize, to: usize) -> D {
+        let mut diff = D::default();
+        for index in descent(ancestor, to, self.branching) {
+            diff.union(self.entry(branch, index));
+        }
+        diff
+    }
+
+    /// The entry at `index` (1-indexed) of the branch, which may be stored by one of the branches
+    /// it forked from.
+    pub fn entry(&self, branch: BranchId, index: usize) -> &D {
+        let stored = &self.branches[self.owner(branch, index).0];
+        let fork = stored.parent.map_or(0, |(_, fork)| fork);
+        &stored.log[index - fork - 1]
+    }
+
+    // the branch which stores the entry at `index` of this branch
+    fn owner(&self, mut branch: BranchId, index: usize) -> BranchId {
+        while let Some((parent, fork)) = self.branches[branch.0].parent {
+            if index > fork {
+                break;
+            }
+            branch = parent;
+        }
+        branch
+    }
+
+    /// The number of entries in the history of the branch, including the shared ones.
+    pub fn len(&self, branch: BranchId) -> usize {
+        let stored = &self.branches[branch.0];
+        stored.parent.map_or(0, |(_, fork)| fork) + stored.log.len()
+    }
+
+    /// The current state of the branch.
+    pub fn current(&self, branch: BranchId) -> &State<V> {
+        &self.branches[branch.0].state
+    }
+
+    /// The state every branch started from.
+    pub fn base(&self) -> &State<V> {
+        &self.base
+    }
+
+    /// The branching factor of the skip structure.
+    pub fn branching(&self) -> usize {
+        self.branching
+    }
+
+    /// Every branch of the history, in the order they were created.
+    pub fn branches(&self) -> impl Iterator<Item = BranchId> {
+        (0..self.branches.len()).map(BranchId)
+    }
+
+    /// The branch this one forked from and the index it forked at, or `None` for the root.
+    pub fn fork_point(&self, branch: BranchId) -> Option<(BranchId, usize)> {
+        self.branches[branch.0].parent
+    }
+}
+
+impl<V: Value, D: DiffEntries<V>> History<V, D> {
+    /// Merge the changes made on `theirs` since the state at `ancestor` into the head of `ours`.
+    ///
+    /// Both branches must share their history up to `ancestor`. The net change of each side since
+    /// then is composed from its entries; cells which only one side changed, or which both changed
+    /// to the same value, merge cleanly. Every other cell is passed to `resolve`, which returns the
+    /// value it should take, or `None` to report it as a conflict. Append the merged diff to
+    /// `ours` to record the merge.
+    pub fn merge<F>(
+        &self,
+        ancestor: usize,
+        ours: BranchId,
+        theirs: BranchId,
+        mut resolve: F,
+    ) -> Merge<V, D>
+    where
+        F: FnMut(&Conflict<V>) -> Option<V>,
+    {
+        assert!(
+            ancestor <= self.len(ours) && ancestor <= self.len(theirs),
+            "index {} is past the end of the branches",
+            ancestor
+        );
+        assert!(
+            ancestor == 0 || self.owner(ours, ancestor) == self.owner(theirs, ancestor),
+            "the branches diverged before index {}",
+            ancestor
+        );
+        let ours_change = self.diff_between(ours, ancestor, self.len(ours));
+        let theirs_change = self.diff_between(theirs, ancestor, self.len(theirs));
+
+        let mut diff = D::default();
+        let mut conflicts = Vec::new();
+        for (index, base, theirs) in theirs_change.entries() {
+            let Some((_, ours)) = ours_change.get(index) else {
+                diff.record(index, base, theirs);
+                continue;
+            };
+            if ours == theirs {
+                continue;
+            }
+            let conflict = Conflict {
+                index,
+                base,
+                ours,
+                theirs,
+            };
+            match resolve(&conflict) {
+                Some(resolved) => diff.record(index, ours, resolved),
+                None => conflicts.push(conflict),
+            }
+        }
+        diff.retain_changes();
+        conflicts.sort_unstable_by_key(|conflict| conflict.index);
+        Merge { diff, conflicts }
+    }
+
+    /// The net diff of the branch from the state at `from` to the state at `to`, in either
+    /// direction. See [`SnapshotLog::diff_between`].
+    pub fn diff_between(&self, branch: BranchId, from: usize, to: usize) -> D {
+        assert!(
+            from <= self.len(branch) && to <= self.len(branch),
+            "index {} is past the end of the branch",
+            from.max(to)
+        );
+        let ancestor = common_ancestor(from, to, self.branching);
+        net_diff(
+            &self.compose(branch, ancestor, from),
+            &self.compose(branch, ancestor, to),
+        )
+    }
+}
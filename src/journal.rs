@@ -0,0 +1,264 @@
+//! An append-only, file-backed snapshot log which writes every entry as it is appended.
+//!
+//! All integers are little-endian. A journal consists of:
+//!
+//! - a header: the magic number `RSNAPJNL`, the format version (u32), the size of an encoded
+//!   value (u32), the branching factor of the log (u32), the number of cells in a state (u64), the
+//!   base state as one encoded value per cell, and the crc-32 of all of the above (u32);
+//! - one record per diff of the log: the length of its payload in bytes (u64), the crc-32 of that
+//!   length and the payload (u32), and the payload itself, which is the diff as encoded by
+//!   [`crate::file`].
+//!
+//! A crash may leave the last record partially written. Opening the journal keeps the longest
+//! prefix of records whose checksums match and cuts off everything after it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::file::{
+    crc32, read_diff, read_len, read_preamble, read_state, write_diff, write_preamble, write_state,
+};
+use crate::{Diff, DiffEntries, Encode, FormatError, SnapshotLog, State, Value};
+
+/// The magic number at the start of every journal.
+pub const MAGIC: [u8; 8] = *b"RSNAPJNL";
+
+/// How often the journal is flushed to stable storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// After every append; no acknowledged append is ever lost.
+    Always,
+    /// After every n appends (n > 0); at most the last n - 1 appends may be lost.
+    Every(usize),
+    /// Only when [`Journal::sync`] is called, or whenever the OS decides to.
+    Never,
+}
+
+/// A snapshot log which is persisted to a file as it grows.
+#[derive(Debug)]
+pub struct Journal<V, D = Diff<V>> {
+    log: SnapshotLog<V, D>,
+    file: File,
+    policy: SyncPolicy,
+    // the end of the last record we know was written completely
+    end: u64,
+    // the number of appends since the last sync
+    unsynced: usize,
+}
+
+impl<V: Value + Encode, D: DiffEntries<V>> Journal<V, D> {
+    /// Create a journal at `path` which starts from the provided base state, replacing any
+    /// existing file.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        base: State<V>,
+        policy: SyncPolicy,
+    ) -> std::io::Result<Self> {
+        Self::create_with_branching(path, base, 2, policy)
+    }
+
+    /// Like [`Journal::create`], but the log has the given branching factor (see
+    /// [`SnapshotLog::with_branching`]).
+    pub fn create_with_branching<P: AsRef<Path>>(
+        path: P,
+        base: State<V>,
+        branching: usize,
+        policy: SyncPolicy,
+    ) -> std::io::Result<Self> {
+        check_policy(policy);
+        let log = SnapshotLog::with_branching(base, branching);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut header = Vec::new();
+        write_preamble::<_, V>(&mut header, &MAGIC, branching)?;
+        header.extend_from_slice(&(log.base().len() as u64).to_le_bytes());
+        write_state(&mut header, log.base(), &mut vec![0; V::SIZE])?;
+        header.extend_from_slice(&crc32(&header).to_le_bytes());
+
+        // without the base, none of the records can be used, so always make sure it's on disk
+        file.write_all(&header)?;
+        file.sync_all()?;
+
+        Ok(Self {
+            log,
+            file,
+            policy,
+            end: header.len() as u64,
+            unsynced: 0,
+        })
+    }
+
+    /// Open the journal at `path`, recovering every record which was completely written.
+    ///
+    /// A torn or corrupt record, and everything after it, is removed from the file. Records which
+    /// are intact but don't match the states they apply to can't be the result of a crash, so the
+    /// journal is rejected as corrupt instead.
+    pub fn open<P: AsRef<Path>>(path: P, policy: SyncPolicy) -> Result<Self, FormatError> {
+        check_policy(policy);
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut reader = BufReader::new(&mut file);
+
+        // the header must be intact; we can't recover anything without the base
+        let mut header = vec![0; PREAMBLE];
+        reader.read_exact(&mut header)?;
+        let mut preamble = header.as_slice();
+        let branching = read_preamble::<_, V>(&mut preamble, &MAGIC)?;
+        let state_len = read_len(&mut preamble)?;
+        let base_len = state_len
+            .checked_mul(V::SIZE)
+            .ok_or(FormatError::Corrupt("length does not fit in memory"))?;
+        (&mut reader)
+            .take(base_len as u64)
+            .read_to_end(&mut header)?;
+        if header.len() != PREAMBLE + base_len {
+            return Err(FormatError::Truncated);
+        }
+        let mut buf = vec![0; V::SIZE];
+        let base = read_state(&mut &header[PREAMBLE..], state_len, &mut buf)?;
+        let mut checksum = [0; 4];
+        reader.read_exact(&mut checksum)?;
+        if u32::from_le_bytes(checksum) != crc32(&header) {
+            return Err(FormatError::Corrupt("header checksum mismatch"));
+        }
+
+        let mut end = (header.len() + checksum.len()) as u64;
+        let mut log = Vec::new();
+        while let Some(payload) = read_record(&mut reader)? {
+            log.push(read_diff(&mut payload.as_slice(), state_len, &mut buf)?);
+            end += (RECORD_HEADER + payload.len()) as u64;
+        }
+        drop(reader);
+
+        // check the records before touching the file
+        let log = SnapshotLog::try_from_parts(base, log, branching)
+            .map_err(|_| FormatError::Corrupt("a diff does not match the state it applies to"))?;
+
+        // cut off whatever was left behind by an interrupted append
+        if file.metadata()?.len() != end {
+            file.set_len(end)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(end))?;
+
+        Ok(Self {
+            log,
+            file,
+            policy,
+            end,
+            unsynced: 0,
+        })
+    }
+
+    /// Apply the diff to the current state, record it as the next entry of the log and write that
+    /// entry to the journal.
+    ///
+    /// A diff which doesn't match the current state is rejected with [`ErrorKind::InvalidInput`]
+    /// before anything is written, since the journal could not be opened again with it. If the
+    /// write fails, the append is undone, both in memory and on disk.
+    pub fn append(&mut self, diff: D) -> std::io::Result<()> {
+        self.log.try_append(diff).map_err(|_| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "the diff does not match the current state",
+            )
+        })?;
+
+        let mut payload = Vec::new();
+        write_diff(
+            &mut payload,
+            self.log.diffs().last().unwrap(),
+            &mut vec![0; V::SIZE],
+        )?;
+        let len = (payload.len() as u64).to_le_bytes();
+        let mut record = Vec::with_capacity(RECORD_HEADER + payload.len());
+        record.extend_from_slice(&len);
+        record.extend_from_slice(&crc32(&[&len[..], &payload].concat()).to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        if let Err(e) = self.write_record(&record) {
+            self.log.truncate(self.log.len() - 1);
+            // best effort: if this fails too, the next record overwrites the torn one, or it is cut
+            // off when the journal is opened
+            let _ = self.file.set_len(self.end);
+            return Err(e);
+        }
+        self.end += record.len() as u64;
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> std::io::Result<()> {
+        // a failed write may have left the cursor anywhere, so never write after a torn record
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(record)?;
+
+        self.unsynced += 1;
+        let sync = match self.policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::Every(n) => self.unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        if sync {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Flush every record written so far to stable storage.
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        self.file.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// The log recorded by the journal.
+    pub fn log(&self) -> &SnapshotLog<V, D> {
+        &self.log
+    }
+
+    /// Stop journaling and take the log.
+    pub fn into_log(self) -> SnapshotLog<V, D> {
+        self.log
+    }
+}
+
+fn check_policy(policy: SyncPolicy) {
+    assert!(
+        policy != SyncPolicy::Every(0),
+        "syncs must be at least one append apart"
+    );
+}
+
+// the magic number, version, value size, branching factor and state length which start the header
+const PREAMBLE: usize = 8 + 4 + 4 + 4 + 8;
+// the length and checksum which precede every payload
+const RECORD_HEADER: usize = 8 + 4;
+
+// read the payload of the next record, or None if there are no more intact records
+fn read_record<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, FormatError> {
+    let mut header = [0; RECORD_HEADER];
+    let mut read = 0;
+    while read < header.len() {
+        match reader.read(&mut header[read..])? {
+            0 => return Ok(None),
+            n => read += n,
+        }
+    }
+    let (len, checksum) = header.split_at(8);
+
+    // don't trust the length until the checksum agrees, so never allocate more than is there
+    let mut payload = Vec::new();
+    let expected = u64::from_le_bytes(len.try_into().unwrap());
+    reader.take(expected).read_to_end(&mut payload)?;
+    if payload.len() as u64 != expected
+        || u32::from_le_bytes(checksum.try_into().unwrap()) != crc32(&[len, &payload].concat())
+    {
+        return Ok(None);
+    }
+    Ok(Some(payload))
+}
@@ -0,0 +1,682 @@
+//! Incremental snapshot log with log(n) state recovery.
+//!
+//! Every append records a diff against the previous state, but the diff stored in the log spans
+//! back to the nearest power-of-two boundary. Any state can then be recovered by applying one diff
+//! per set bit of its index.
+//!
+//! More generally, with a branching factor of k, the diff stored for index n spans back to n with
+//! its lowest nonzero base-k digit decremented. A state is recovered by applying one diff per unit
+//! of the digit sum of its index, and every append updates one cached diff per digit of the length.
+
+use std::collections::{BTreeMap, HashMap};
+
+// consistency check which runs in debug builds, or in all builds with the `checks` feature
+macro_rules! check {
+    ($($arg:tt)*) => {
+        if cfg!(any(debug_assertions, feature = "checks")) {
+            assert!($($arg)*);
+        }
+    };
+}
+
+mod cursor;
+pub mod diff;
+mod error;
+pub mod file;
+pub mod history;
+pub mod journal;
+pub mod mapped;
+mod retention;
+pub mod tiered;
+
+pub use cursor::Cursor;
+pub use diff::{
+    AdaptiveDiff, DenseDiff, DiffBackend, DiffEntries, ForwardDiff, RangeDiff, Reversible,
+    SortedDiff, XorDiff,
+};
+pub use error::{DiffError, FormatError};
+pub use file::Encode;
+pub use history::{BranchId, Conflict, History, Merge};
+pub use journal::{Journal, SyncPolicy};
+pub use mapped::MappedLog;
+pub use retention::Retention;
+pub use tiered::TieredLog;
+
+/// The values which may be recorded in the cells of a state.
+pub trait Value: Copy + Eq {}
+
+impl<T: Copy + Eq> Value for T {}
+
+// the diff, representing the difference between two states (the default representation)
+pub type Diff<V> = HashMap<usize, (V, V)>;
+// the state itself
+pub type State<V> = Vec<V>;
+// a cache for recording the diffs between multiple states
+pub type DiffCache<D> = Vec<D>;
+// the log of all the states we have seen so far as encoded in diffs
+pub type StateLog<D> = Vec<D>;
+// full copies of some of the states in the log, by index
+pub type Checkpoints<V> = BTreeMap<usize, State<V>>;
+
+/// The direction in which a state is recovered from the log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Recovery {
+    /// Apply diffs onto the base state, or start from the checkpoint nearest to the target.
+    Forward,
+    /// Revert diffs from the current state, then apply any needed to reach the target.
+    Backward,
+    /// Pick whichever of the two needs fewer diffs.
+    #[default]
+    Auto,
+}
+
+/// When a log stores a full copy of its current state after an append. By default, it never does.
+///
+/// Forward recovery starts from the checkpoint nearest to the target rather than the base, which
+/// skips the largest diffs on the way. Checkpoints are only kept in memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CheckpointPolicy {
+    /// Store a checkpoint every this many appends. Unless this is a power of the branching factor,
+    /// only logs of [`Reversible`] diffs can start from the checkpoint before the target, since it
+    /// is not on the path from the base to the target.
+    pub every: Option<usize>,
+    /// Store a checkpoint when the diff stored for an append changes more than this fraction of
+    /// the cells of the state.
+    pub max_fraction: Option<f64>,
+}
+
+/// A log of states, recorded as diffs, which supports log(n) recovery of any previous state.
+///
+/// The diffs are stored as `D`, which is a [`Diff`] unless another [`DiffBackend`] is picked.
+#[derive(Clone, Debug)]
+pub struct SnapshotLog<V, D = Diff<V>> {
+    base: State<V>,
+    log: StateLog<D>,
+    cache: DiffCache<D>,
+    state: State<V>,
+    branching: usize,
+    checkpoints: Checkpoints<V>,
+    policy: CheckpointPolicy,
+    // the original indices of the states at the start of the log which were renumbered when it
+    // was pruned or rebased; the states after them follow on from the last one
+    origins: Vec<usize>,
+}
+
+impl<V: Value + Default, D: DiffBackend<V>> SnapshotLog<V, D> {
+    /// Create an empty log whose initial state is `size` default cells.
+    pub fn new(size: usize) -> Self {
+        Self::from_base(initial_state(size))
+    }
+}
+
+impl<V: Value, D: DiffBackend<V>> SnapshotLog<V, D> {
+    /// Create an empty log which starts from the provided base state.
+    pub fn from_base(base: State<V>) -> Self {
+        Self::with_branching(base, 2)
+    }
+
+    /// Create an empty log which starts from the provided base state, with the given branching
+    /// factor (at least 2). Larger factors make appends cheaper, but recovery applies more diffs.
+    pub fn with_branching(base: State<V>, branching: usize) -> Self {
+        assert!(branching >= 2, "the branching factor must be at least 2");
+        Self {
+            state: base.clone(),
+            base,
+            log: StateLog::new(),
+            cache: vec![D::default()], // initialize the cache
+            branching,
+            checkpoints: Checkpoints::new(),
+            policy: CheckpointPolicy::default(),
+            origins: Vec::new(),
+        }
+    }
+
+    /// Reassemble a log from its base state and the diffs it recorded with the given branching
+    /// factor, e.g. after deserializing them. The current state and the cache needed to keep
+    /// appending are rebuilt from the diffs.
+    pub fn from_parts(base: State<V>, mut log: StateLog<D>, branching: usize) -> Self {
+        assert!(branching >= 2, "the branching factor must be at least 2");
+        log.iter_mut().for_each(D::compact);
+        Self {
+            state: recover_state(&log, &base, 0, log.len(), branching),
+            cache: rebuild_cache(&log, branching),
+            base,
+            log,
+            branching,
+            checkpoints: Checkpoints::new(),
+            policy: CheckpointPolicy::default(),
+            origins: Vec::new(),
+        }
+    }
+
+    /// Like [`SnapshotLog::from_parts`], but verifies every diff against the state it starts from
+    /// instead of trusting it, e.g. when the diffs were read from a file.
+    pub fn try_from_parts(
+        base: State<V>,
+        mut log: StateLog<D>,
+        branching: usize,
+    ) -> Result<Self, DiffError<V>>
+    where
+        D: DiffEntries<V>,
+    {
+        assert!(branching >= 2, "the branching factor must be at least 2");
+        log.iter_mut().for_each(D::compact);
+        Ok(Self {
+            state: replay(&log, &base, branching)?,
+            cache: rebuild_cache(&log, branching),
+            base,
+            log,
+            branching,
+            checkpoints: Checkpoints::new(),
+            policy: CheckpointPolicy::default(),
+            origins: Vec::new(),
+        })
+    }
+
+    /// Apply the diff to the current state and record it as the next entry of the log.
+    pub fn append(&mut self, diff: D) {
+        diff.apply(&mut self.state);
+        append_diff(&mut self.log, &mut self.cache, diff, self.branching);
+        self.checkpoint();
+    }
+
+    /// Like [`SnapshotLog::append`], but verifies the diff against the current state first.
+    ///
+    /// On error, neither the state nor the log are modified.
+    pub fn try_append(&mut self, diff: D) -> Result<(), DiffError<V>>
+    where
+        D: DiffEntries<V>,
+    {
+        diff.try_apply(&mut self.state)?;
+        append_diff(&mut self.log, &mut self.cache, diff, self.branching);
+        self.checkpoint();
+        Ok(())
+    }
+
+    // store a checkpoint of the current state if the policy asks for one
+    fn checkpoint(&mut self) {
+        let len = self.log.len();
+        let every = self
+            .policy
+            .every
+            .is_some_and(|every| len.is_multiple_of(every));
+        let large = self.policy.max_fraction.is_some_and(|fraction| {
+            self.log[len - 1].len() as f64 > fraction * self.state.len() as f64
+        });
+        if every || large {
+            self.checkpoints.insert(len, self.state.clone());
+        }
+    }
+
+    /// Set when checkpoints are stored from now on. Existing checkpoints are kept.
+    pub fn set_checkpoint_policy(&mut self, policy: CheckpointPolicy) {
+        assert!(
+            policy.every != Some(0),
+            "checkpoints must be at least one append apart"
+        );
+        self.policy = policy;
+    }
+
+    /// The indices of the states stored as checkpoints.
+    pub fn checkpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.checkpoints.keys().copied()
+    }
+
+    // the nearest state on the path to `index` which is stored in full, and its index
+    fn nearest_checkpoint(&self, index: usize) -> (usize, &State<V>) {
+        let mut ancestor = index;
+        while ancestor != 0 {
+            if let Some(state) = self.checkpoints.get(&ancestor) {
+                return (ancestor, state);
+            }
+            ancestor = parent(ancestor, self.branching);
+        }
+        (0, &self.base)
+    }
+
+    /// Recover the state at `index` by applying diffs onto the base state, or the nearest
+    /// checkpoint on the way. Unlike [`SnapshotLog::recover`], this works for diffs which cannot be
+    /// reverted.
+    pub fn recover_forward(&self, index: usize) -> State<V> {
+        assert!(
+            index <= self.len(),
+            "index {} is past the end of the log",
+            index
+        );
+        let (start, state) = self.nearest_checkpoint(index);
+        recover_state(&self.log, state, start, index, self.branching)
+    }
+
+    /// The branching factor of the skip structure.
+    pub fn branching(&self) -> usize {
+        self.branching
+    }
+
+    /// The number of entries in the log.
+    pub fn len(&self) -> usize {
+        self.log.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.log.is_empty()
+    }
+
+    /// The base state, i.e. the state at index 0.
+    pub fn base(&self) -> &State<V> {
+        &self.base
+    }
+
+    /// The index which the state at `index` had before the log was pruned or rebased, i.e. the
+    /// number of diffs appended before it since the log was created.
+    pub fn origin(&self, index: usize) -> usize {
+        match (self.origins.get(index), self.origins.last()) {
+            (Some(&origin), _) => origin,
+            (None, Some(&last)) => last + index + 1 - self.origins.len(),
+            (None, None) => index,
+        }
+    }
+
+    /// The current (most recent) state.
+    pub fn current(&self) -> &State<V> {
+        &self.state
+    }
+
+    /// The diffs stored in the log; entry `i` leads to the state at index `i + 1`.
+    pub fn diffs(&self) -> &[D] {
+        &self.log
+    }
+
+    /// The accumulated diffs from which the next entries of the log will be built.
+    pub fn cache(&self) -> &[D] {
+        &self.cache
+    }
+}
+
+/// Comparing states requires diffs whose entries can be read.
+impl<V: Value, D: DiffEntries<V>> SnapshotLog<V, D> {
+    /// The net diff from the state at `from` to the state at `to`, holding exactly the cells which
+    /// differ between them.
+    ///
+    /// It is composed from the diffs on the paths from the two indices to their common ancestor,
+    /// without recovering either state. If `from` is after `to`, the diff undoes the changes made
+    /// in between.
+    pub fn diff_between(&self, from: usize, to: usize) -> D {
+        assert!(
+            from <= self.len() && to <= self.len(),
+            "index {} is past the end of the log",
+            from.max(to)
+        );
+        let ancestor = common_ancestor(from, to, self.branching);
+        net_diff(
+            &compose_descent(&self.log, ancestor, from, self.branching),
+            &compose_descent(&self.log, ancestor, to, self.branching),
+        )
+    }
+}
+
+/// Moving back from the current state requires diffs which can be reverted.
+impl<V: Value, D: Reversible<V>> SnapshotLog<V, D> {
+    /// Recover the state at `index`, where 0 is the initial state and `len()` is the current one.
+    pub fn recover(&self, index: usize) -> State<V> {
+        self.recover_with(index, Recovery::Auto)
+    }
+
+    /// Recover the state at `index`, starting from the base or current state as requested.
+    pub fn recover_with(&self, index: usize, recovery: Recovery) -> State<V> {
+        assert!(
+            index <= self.len(),
+            "index {} is past the end of the log",
+            index
+        );
+
+        let backward = match recovery {
+            Recovery::Forward => false,
+            Recovery::Backward => true,
+            Recovery::Auto => {
+                let (start, _) = self.nearest_start(index);
+                path_len(self.len(), index, self.branching) < path_len(start, index, self.branching)
+            }
+        };
+
+        let (start, state) = if backward {
+            (self.len(), &self.state)
+        } else {
+            self.nearest_start(index)
+        };
+        let mut state = state.clone();
+        seek_state(&self.log, &mut state, start, index, self.branching);
+        state
+    }
+
+    // the state stored in full from which the fewest diffs lead to `index`: the latest checkpoint
+    // at or before it, or the nearest one on its path from the base
+    fn nearest_start(&self, index: usize) -> (usize, &State<V>) {
+        let (start, state) = self.nearest_checkpoint(index);
+        match self.checkpoints.range(..=index).next_back() {
+            Some((&before, checkpoint))
+                if path_len(before, index, self.branching)
+                    < path_len(start, index, self.branching) =>
+            {
+                (before, checkpoint)
+            }
+            _ => (start, state),
+        }
+    }
+
+    /// Discard every state after `index`, making the state at `index` the current one.
+    ///
+    /// Subsequent appends continue from that state.
+    pub fn truncate(&mut self, index: usize) {
+        self.state = self.recover(index);
+        self.log.truncate(index);
+        self.cache = rebuild_cache(&self.log, self.branching);
+        self.checkpoints.split_off(&(index + 1));
+        self.origins.truncate(index + 1);
+        trim_origins(&mut self.origins);
+    }
+
+    /// Create a cursor holding the state at `index`, to be moved to nearby states.
+    pub fn cursor(&self, index: usize) -> Cursor<'_, V, D> {
+        Cursor::new(self, index)
+    }
+}
+
+// drop the original indices at the end which follow on from the previous one, since they are
+// implied by it
+fn trim_origins(origins: &mut Vec<usize>) {
+    while let [.., previous, last] = origins[..] {
+        if last != previous + 1 {
+            break;
+        }
+        origins.pop();
+    }
+}
+
+/// The state with `size` default (usually zero) cells.
+pub fn initial_state<V: Value + Default>(size: usize) -> State<V> {
+    vec![V::default(); size]
+}
+
+// the number of trailing zero digits of a nonzero index in base k
+fn trailing_digits(index: usize, k: usize) -> u32 {
+    if k.is_power_of_two() {
+        return index.trailing_zeros() / k.trailing_zeros();
+    }
+    let mut index = index;
+    let mut digits = 0;
+    while index.is_multiple_of(k) {
+        index /= k;
+        digits += 1;
+    }
+    digits
+}
+
+// the number of digits of an index in base k
+fn digits(index: usize, k: usize) -> u32 {
+    if k.is_power_of_two() {
+        return (usize::BITS - index.leading_zeros()).div_ceil(k.trailing_zeros());
+    }
+    let mut index = index;
+    let mut digits = 0;
+    while index != 0 {
+        index /= k;
+        digits += 1;
+    }
+    digits
+}
+
+// the index with its lowest `digits` digits in base k cleared
+fn clear_low(index: usize, digits: u32, k: usize) -> usize {
+    k.checked_pow(digits).map_or(0, |unit| index - index % unit)
+}
+
+// the index of the state that the diff leading to this index starts from
+fn parent(index: usize, k: usize) -> usize {
+    index - k.pow(trailing_digits(index, k))
+}
+
+// the latest state from which both indices can be reached by only applying diffs
+fn common_ancestor(mut from: usize, mut to: usize, k: usize) -> usize {
+    while from != to {
+        if from > to {
+            from = parent(from, k);
+        } else {
+            to = parent(to, k);
+        }
+    }
+    from
+}
+
+// the number of diffs which must be reverted or applied to move between the two indices
+fn path_len(from: usize, to: usize, k: usize) -> usize {
+    let ancestor = common_ancestor(from, to, k);
+    let mut len = 0;
+    for mut index in [from, to] {
+        while index != ancestor {
+            index = parent(index, k);
+            len += 1;
+        }
+    }
+    len
+}
+
+// move the state at index `from` to the state at index `to`, going through their common ancestor
+fn seek_state<V: Value, D: Reversible<V>>(
+    log: &[D],
+    state: &mut [V],
+    from: usize,
+    to: usize,
+    k: usize,
+) {
+    let ancestor = common_ancestor(from, to, k);
+
+    // walk back up the structure from the state we hold
+    let mut index = from;
+    while index != ancestor {
+        log[index - 1].revert(state);
+        index = parent(index, k);
+    }
+
+    // then walk down to the target
+    for index in descent(ancestor, to, k) {
+        log[index - 1].apply(state);
+    }
+}
+
+// the indices whose diffs lead from `ancestor` down to `to`, in the order they must be applied
+fn descent(ancestor: usize, to: usize, k: usize) -> Vec<usize> {
+    // we can only discover the path from the bottom up
+    let mut path = Vec::new();
+    let mut index = to;
+    while index > ancestor {
+        path.push(index);
+        index = parent(index, k);
+    }
+    debug_assert!(
+        index == ancestor,
+        "{} is not an ancestor of {}",
+        ancestor,
+        to
+    );
+
+    path.reverse();
+    path
+}
+
+// the net diff from the state at `ancestor` to the state at `to`
+fn compose_descent<V: Value, D: DiffBackend<V>>(
+    log: &[D],
+    ancestor: usize,
+    to: usize,
+    k: usize,
+) -> D {
+    let mut diff = D::default();
+    for index in descent(ancestor, to, k) {
+        diff.union(&log[index - 1]);
+    }
+    diff
+}
+
+// the net diff from the state at one index to the state at another, given the net diffs to each
+// of them from their common ancestor; a cell which only one side wrote holds the same value at the
+// ancestor and on the other side
+fn net_diff<V: Value, D: DiffEntries<V>>(to_from: &D, to_to: &D) -> D {
+    let mut diff = D::default();
+    for (index, orig, from) in to_from.entries() {
+        let to = to_to.get(index).map_or(orig, |(_, to)| to);
+        diff.record(index, from, to);
+    }
+    for (index, orig, to) in to_to.entries() {
+        if to_from.get(index).is_none() {
+            diff.record(index, orig, to);
+        }
+    }
+    diff.retain_changes();
+    diff
+}
+
+// recover this state by progressively applying diffs to the state at `start`, one of its ancestors
+fn recover_state<V: Value, D: DiffBackend<V>>(
+    log: &[D],
+    start: &State<V>,
+    from: usize,
+    index: usize,
+    k: usize,
+) -> State<V> {
+    let mut state = start.clone();
+
+    // every diff on the path down to the index, most significant first
+    for index in descent(from, index, k) {
+        log[index - 1].apply(&mut state);
+    }
+
+    state
+}
+
+// apply every entry of the log to the state it starts from, verifying each of them, and return
+// the last state
+fn replay<V: Value, D: DiffEntries<V>>(
+    log: &[D],
+    base: &State<V>,
+    k: usize,
+) -> Result<State<V>, DiffError<V>> {
+    let mut state = base.clone();
+    for index in 1..=log.len() {
+        // entry n starts from the state at the previous index with its lowest digits cleared,
+        // which is on the path to the state we hold; everything reverted was verified already
+        let mut at = index - 1;
+        while at != parent(index, k) {
+            log[at - 1].revert(&mut state);
+            at = parent(at, k);
+        }
+        log[index - 1].try_apply(&mut state)?;
+    }
+    Ok(state)
+}
+
+/// Rebuild the cache that appending expects after every diff in the log was appended with the
+/// given branching factor, e.g. if only the log itself was persisted.
+pub fn rebuild_cache<V: Value, D: DiffBackend<V>>(log: &[D], branching: usize) -> DiffCache<D> {
+    let len = log.len();
+    let depth = digits(len, branching);
+
+    // the bottom of the cache always spans from the base; each entry above it starts from the
+    // state with one fewer low digits of the length cleared, down to two digits (the diffs which
+    // only span one digit are built from the log instead)
+    (0..cache_len(len, branching))
+        .map(|level| {
+            let ancestor = clear_low(len, depth - level as u32, branching);
+            compose_descent(log, ancestor, len, branching)
+        })
+        .collect()
+}
+
+// the number of diffs in the cache of a log of this length
+fn cache_len(len: usize, k: usize) -> usize {
+    digits(len, k).saturating_sub(1).max(1) as usize
+}
+
+// create a diff from the most recent relevant cached diff
+fn append_diff<V: Value, D: DiffBackend<V>>(
+    log: &mut StateLog<D>,
+    cache: &mut DiffCache<D>,
+    diff: D,
+    k: usize,
+) {
+    let len = log.len();
+    let entry = next_entry(cache, diff, len, k, |ancestor| {
+        compose_descent(log, ancestor, len, k)
+    });
+    log.push(entry);
+}
+
+// whether building the entry which follows `len` entries composes the most recent entries, since
+// the lowest digit of the new index wraps around without starting a new most significant digit
+pub(crate) fn needs_recent(len: usize, k: usize) -> bool {
+    let level = trailing_digits(len + 1, k);
+    level == 1 && level != digits(len, k)
+}
+
+// build the entry of the log which follows `len` entries from the diff which was appended,
+// updating the cache; `recent` must compose the entries since the given index, which are only
+// needed when the lowest digit of the new index wraps around
+//
+// entry p of the cache holds the diff from the current state with its lowest `depth - p` digits
+// cleared, where `depth` is the number of digits of the current index
+pub(crate) fn next_entry<V: Value, D: DiffBackend<V>>(
+    cache: &mut DiffCache<D>,
+    mut diff: D,
+    len: usize,
+    k: usize,
+    recent: impl FnOnce(usize) -> D,
+) -> D {
+    // drop writes which don't change anything, so that every diff we store is exactly the set of
+    // cells which changed; this makes the stored diffs independent of how they were composed
+    diff.retain_changes();
+
+    let index = len + 1;
+    let level = trailing_digits(index, k);
+    let depth = digits(len, k);
+
+    let mut entry = if level == 0 {
+        // the diff only spans this append
+        for cached in cache.iter_mut() {
+            cached.union(&diff);
+        }
+        diff
+    } else if level == depth {
+        // a new most significant digit: everything so far is one diff, and every other level
+        // starts afresh
+        let mut cached = std::mem::take(&mut cache[0]);
+        cached.union(&diff); // diff is probably smaller
+        cache.clear();
+        cache.push(cached.clone());
+        cache.resize(cache_len(index, k), D::default());
+        cached
+    } else if level == 1 {
+        // the lowest digit isn't cached, as it only spans fewer than k appends
+        for cached in cache.iter_mut() {
+            cached.union(&diff);
+        }
+        let mut entry = recent(clear_low(len, 1, k));
+        entry.union(&diff);
+        entry
+    } else {
+        // this level has been accumulating since its digit last changed; the ones above carry on
+        let split = (depth - level) as usize;
+        for cached in &mut cache[..split] {
+            cached.union(&diff);
+        }
+        let mut entry = std::mem::take(&mut cache[split]);
+        entry.union(&diff); // diff is probably smaller
+
+        // the levels below haven't seen any change since the one we just took
+        for cached in &mut cache[split + 1..] {
+            *cached = D::default();
+        }
+        entry
+    };
+
+    entry.compact();
+    entry
+}
@@ -0,0 +1,241 @@
+//! A read-only format for snapshot logs which is read in place from a memory-mapped file.
+//!
+//! All integers are little-endian. A file consists of:
+//!
+//! - a header: the magic number `RSNAPMAP`, the format version (u32), the size of an encoded
+//!   value (u32), the branching factor of the log (u32), the number of cells in a state and the
+//!   number of diffs in the log (each u64);
+//! - the base state, as one encoded value per cell;
+//! - an offset table of one u64 per diff, giving the file offset of its first entry, followed by
+//!   the offset of the end of the last diff;
+//! - the entries of every diff, each `(index: u64, orig, new)`, sorted by index.
+//!
+//! Since every entry has the same width, a diff is just a slice of the file, and recovering a state
+//! only touches the diffs on its path instead of deserializing the whole log.
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::file::{read_len, read_preamble, write_preamble, write_state};
+use crate::{descent, DiffEntries, Encode, FormatError, SnapshotLog, State, Value};
+
+/// The magic number at the start of every memory-mappable log.
+pub const MAGIC: [u8; 8] = *b"RSNAPMAP";
+
+// the magic number, version, value size, branching factor, state length and log length
+const HEADER: usize = 8 + 4 + 4 + 4 + 8 + 8;
+
+impl<V: Value + Encode, D: DiffEntries<V>> SnapshotLog<V, D> {
+    /// Write the log in the format read by [`MappedLog`]. Unlike [`SnapshotLog::save`], the cache
+    /// is not written, so the log cannot be extended once it is written this way.
+    pub fn save_mappable<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+
+        write_preamble::<_, V>(&mut writer, &MAGIC, self.branching)?;
+        for len in [self.base.len(), self.log.len()] {
+            writer.write_all(&(len as u64).to_le_bytes())?;
+        }
+        let mut buf = vec![0; V::SIZE];
+        write_state(&mut writer, &self.base, &mut buf)?;
+
+        let entry = entry_size::<V>() as u64;
+        let mut offset = (HEADER + self.base.len() * V::SIZE + (self.log.len() + 1) * 8) as u64;
+        for diff in &self.log {
+            writer.write_all(&offset.to_le_bytes())?;
+            offset += diff.len() as u64 * entry;
+        }
+        writer.write_all(&offset.to_le_bytes())?;
+
+        let mut entries = Vec::new();
+        for diff in &self.log {
+            entries.clear();
+            entries.extend(diff.entries());
+            entries.sort_unstable_by_key(|&(index, _, _)| index);
+            for &(index, orig, new) in &entries {
+                writer.write_all(&(index as u64).to_le_bytes())?;
+                orig.encode(&mut buf);
+                writer.write_all(&buf)?;
+                new.encode(&mut buf);
+                writer.write_all(&buf)?;
+            }
+        }
+
+        writer.flush()
+    }
+}
+
+/// A read-only snapshot log which is read in place from a file written by
+/// [`SnapshotLog::save_mappable`].
+#[derive(Debug)]
+pub struct MappedLog<V> {
+    map: Mmap,
+    state_len: usize,
+    len: usize,
+    branching: usize,
+    _value: PhantomData<V>,
+}
+
+impl<V: Value + Encode> MappedLog<V> {
+    /// Map the file at `path`.
+    ///
+    /// Only the header and offset table are checked here; the diffs are not read until they are
+    /// needed. The file must not be modified while it is mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FormatError> {
+        let file = File::open(path)?;
+        // SAFETY: we only ever read from the map, and we require that the file is not modified
+        // while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+
+        let mut header = map.get(..HEADER).ok_or(FormatError::Truncated)?;
+        let branching = read_preamble::<_, V>(&mut header, &MAGIC)?;
+        let state_len = read_len(&mut header)?;
+        let len = read_len(&mut header)?;
+
+        let log = Self {
+            map,
+            state_len,
+            len,
+            branching,
+            _value: PhantomData,
+        };
+
+        // the table must fit, and the diffs it points to must be whole entries in the file
+        let table_end = state_len
+            .checked_mul(V::SIZE)
+            .and_then(|base| base.checked_add(HEADER))
+            .and_then(|start| len.checked_add(1)?.checked_mul(8)?.checked_add(start))
+            .ok_or(FormatError::Corrupt("length does not fit in memory"))?;
+        if log.map.len() < table_end {
+            return Err(FormatError::Truncated);
+        }
+        let mut previous = table_end;
+        for i in 0..=len {
+            let offset = log.offset(i);
+            if offset < previous
+                || !(offset - previous).is_multiple_of(entry_size::<V>())
+                || (i == 0 && offset != table_end)
+            {
+                return Err(FormatError::Corrupt("offset table is inconsistent"));
+            }
+            previous = offset;
+        }
+        match previous.cmp(&log.map.len()) {
+            Ordering::Less => Err(FormatError::Corrupt("trailing data after the last record")),
+            Ordering::Equal => Ok(log),
+            Ordering::Greater => Err(FormatError::Truncated),
+        }
+    }
+
+    /// The number of entries in the log.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of cells in every state.
+    pub fn state_len(&self) -> usize {
+        self.state_len
+    }
+
+    /// The base state, i.e. the state at index 0.
+    pub fn base(&self) -> State<V> {
+        self.map[HEADER..HEADER + self.state_len * V::SIZE]
+            .chunks_exact(V::SIZE)
+            .map(V::decode)
+            .collect()
+    }
+
+    /// Recover the state at `index`, reading only the diffs on its path.
+    pub fn recover(&self, index: usize) -> Result<State<V>, FormatError> {
+        assert!(
+            index <= self.len,
+            "index {} is past the end of the log",
+            index
+        );
+
+        let mut state = self.base();
+        for index in descent(0, index, self.branching) {
+            for (cell, _, new) in self.entries(index - 1) {
+                *state.get_mut(cell).ok_or(FormatError::Corrupt(
+                    "diff refers to a cell outside the state",
+                ))? = new;
+            }
+        }
+        Ok(state)
+    }
+
+    /// The value of a single cell in the state at `index`, found by searching the diffs on its
+    /// path rather than recovering the whole state.
+    pub fn value_at(&self, index: usize, cell: usize) -> V {
+        assert!(
+            index <= self.len,
+            "index {} is past the end of the log",
+            index
+        );
+        assert!(cell < self.state_len, "cell {} is out of range", cell);
+
+        // the last diff on the path which touches the cell decides its value
+        for index in descent(0, index, self.branching).into_iter().rev() {
+            if let Some((_, _, new)) = self.find(index - 1, cell) {
+                return new;
+            }
+        }
+        let start = HEADER + cell * V::SIZE;
+        V::decode(&self.map[start..start + V::SIZE])
+    }
+
+    // the file offset of the first entry of diff `i`, or of the end of the last diff
+    fn offset(&self, i: usize) -> usize {
+        let start = HEADER + self.state_len * V::SIZE + i * 8;
+        u64::from_le_bytes(self.map[start..start + 8].try_into().unwrap()) as usize
+    }
+
+    fn records(&self, i: usize) -> &[u8] {
+        &self.map[self.offset(i)..self.offset(i + 1)]
+    }
+
+    fn entries(&self, i: usize) -> impl Iterator<Item = (usize, V, V)> + '_ {
+        self.records(i)
+            .chunks_exact(entry_size::<V>())
+            .map(decode_entry)
+    }
+
+    // binary search diff `i` for the entry of the given cell
+    fn find(&self, i: usize, cell: usize) -> Option<(usize, V, V)> {
+        let records = self.records(i);
+        let (mut lo, mut hi) = (0, records.len() / entry_size::<V>());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let start = mid * entry_size::<V>();
+            let entry = decode_entry::<V>(&records[start..start + entry_size::<V>()]);
+            match entry.0.cmp(&cell) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Equal => return Some(entry),
+                Ordering::Greater => hi = mid,
+            }
+        }
+        None
+    }
+}
+
+const fn entry_size<V: Encode>() -> usize {
+    8 + 2 * V::SIZE
+}
+
+fn decode_entry<V: Encode>(entry: &[u8]) -> (usize, V, V) {
+    let (index, values) = entry.split_at(8);
+    let (orig, new) = values.split_at(V::SIZE);
+    (
+        u64::from_le_bytes(index.try_into().unwrap()) as usize,
+        V::decode(orig),
+        V::decode(new),
+    )
+}
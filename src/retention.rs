@@ -0,0 +1,148 @@
+use crate::{
+    common_ancestor, descent, parent, rebuild_cache, seek_state, trim_origins, DiffEntries,
+    SnapshotLog, StateLog, Value,
+};
+
+/// Which states a log keeps when it is pruned with [`SnapshotLog::prune`].
+///
+/// For example, keeping every state from the last 10000 appends and only every 1000th state
+/// before them is `Retention { recent: 10_000, every: Some(1000) }`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retention {
+    /// Keep every state among the last this many appends, as well as the current state.
+    pub recent: usize,
+    /// Before those, keep the states whose original index (see [`SnapshotLog::origin`]) is a
+    /// multiple of this, so that pruning again with the same policy keeps them all. If unset,
+    /// they are all dropped and the oldest state which is kept becomes the base.
+    pub every: Option<usize>,
+}
+
+impl<V: Value, D: DiffEntries<V>> SnapshotLog<V, D> {
+    /// Drop the states which the retention policy doesn't keep, and renumber the rest.
+    ///
+    /// The oldest state which is kept becomes the base, and the others are recorded as diffs from
+    /// the previous state which is kept, so every kept state can be recovered as before. Returns
+    /// the old index of every kept state: the state at index i after pruning is the state at index
+    /// `kept[i]` before. Checkpoints of kept states are kept as well, and so is the original index
+    /// of every kept state (see [`SnapshotLog::origin`]).
+    pub fn prune(&mut self, retention: Retention) -> Vec<usize> {
+        assert!(
+            retention.every != Some(0),
+            "kept states must be at least one append apart"
+        );
+        let len = self.len();
+        let recent = len.saturating_sub(retention.recent);
+        let mut kept: Vec<usize> = match retention.every {
+            Some(every) => (0..recent)
+                .filter(|&index| self.origin(index).is_multiple_of(every))
+                .collect(),
+            None => Vec::new(),
+        };
+        kept.extend(recent..=len);
+
+        let k = self.branching;
+        let mut state = self.recover(kept[0]);
+        let mut pruned = SnapshotLog::with_branching(state.clone(), k);
+        for pair in kept.windows(2) {
+            pruned.append(seek_diff(&self.log, &mut state, pair[0], pair[1], k));
+        }
+        check!(pruned.state == self.state);
+
+        pruned.checkpoints = std::mem::take(&mut self.checkpoints)
+            .into_iter()
+            .filter_map(|(index, state)| Some((kept.binary_search(&index).ok()?, state)))
+            .filter(|&(index, _)| index != 0)
+            .collect();
+        pruned.policy = self.policy;
+        pruned.origins = self.origins_of(kept.iter().copied());
+        *self = pruned;
+        kept
+    }
+
+    /// Make the state at `index` the base of the log, dropping every state before it. The state
+    /// at index i after rebasing is the state at index `index + i` before.
+    ///
+    /// Every entry of the log is rebuilt as the diff between the old states it now spans, and the
+    /// cache is rebuilt from them, so nothing is appended again. Checkpoints after `index` are
+    /// kept.
+    pub fn rebase(&mut self, index: usize) {
+        assert!(
+            index <= self.len(),
+            "index {} is past the end of the log",
+            index
+        );
+        let k = self.branching;
+        let mut state = self.recover(index);
+        let base = state.clone();
+
+        // entry j of the rebased log spans from its parent to j, shifted by the new origin
+        let mut at = index;
+        let log: StateLog<D> = (1..=self.len() - index)
+            .map(|j| {
+                let from = index + parent(j, k);
+                seek_state(&self.log, &mut state, at, from, k);
+                let mut entry = seek_diff(&self.log, &mut state, from, index + j, k);
+                at = index + j;
+                entry.retain_changes();
+                entry.compact();
+                entry
+            })
+            .collect();
+        check!(state == self.state);
+
+        self.origins = self.origins_of(index..=self.len());
+        self.cache = rebuild_cache(&log, k);
+        self.log = log;
+        self.base = base;
+        self.checkpoints = std::mem::take(&mut self.checkpoints)
+            .split_off(&(index + 1))
+            .into_iter()
+            .map(|(checkpoint, state)| (checkpoint - index, state))
+            .collect();
+    }
+
+    // the original indices of the given states, once they are renumbered from 0
+    fn origins_of(&self, states: impl Iterator<Item = usize>) -> Vec<usize> {
+        let mut origins: Vec<usize> = states.map(|index| self.origin(index)).collect();
+        trim_origins(&mut origins);
+        origins
+    }
+}
+
+// move the state at index `from` to the state at index `to`, returning the diff between them
+fn seek_diff<V: Value, D: DiffEntries<V>>(
+    log: &[D],
+    state: &mut [V],
+    from: usize,
+    to: usize,
+    k: usize,
+) -> D {
+    // only the cells which the diffs between the two states touch can differ
+    let cells = touched(log, from, to, k);
+    let orig: Vec<V> = cells.iter().map(|&cell| state[cell]).collect();
+    seek_state(log, state, from, to, k);
+    let mut diff = D::default();
+    for (&cell, orig) in cells.iter().zip(orig) {
+        diff.record(cell, orig, state[cell]);
+    }
+    diff
+}
+
+// every cell written by the diffs on the path between the two indices, without duplicates
+fn touched<V: Value, D: DiffEntries<V>>(log: &[D], from: usize, to: usize, k: usize) -> Vec<usize> {
+    let ancestor = common_ancestor(from, to, k);
+    let mut path = descent(ancestor, to, k);
+    let mut index = from;
+    while index != ancestor {
+        path.push(index);
+        index = parent(index, k);
+    }
+
+    let mut cells: Vec<usize> = path
+        .into_iter()
+        .flat_map(|index| log[index - 1].entries().map(|(cell, _, _)| cell))
+        .collect();
+    cells.sort_unstable();
+    cells.dedup();
+    cells
+}
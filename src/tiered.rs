@@ -0,0 +1,351 @@
+//! A snapshot log which keeps only as many diffs in memory as a budget allows.
+//!
+//! Whenever the diffs held in memory exceed the budget, the least recently used ones are written to
+//! segment files in a directory of the caller's choosing and dropped. A diff which is needed again,
+//! to recover a state or to build a new entry, is read back transparently. Every diff is written at
+//! most once, since diffs never change after they are appended, so dropping it again is free.
+//!
+//! Spilled diffs are written as encoded by [`crate::file`], one after the other. The segment files
+//! are scratch space which only this log can read: each log keeps them in a subdirectory of its
+//! own, which is deleted when the log is dropped.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::file::{read_diff, write_diff};
+use crate::{
+    descent, needs_recent, next_entry, Diff, DiffCache, DiffEntries, Encode, FormatError, State,
+    Value,
+};
+
+// segments are closed once they grow past this many bytes, so that no single file grows unbounded
+const SEGMENT_SIZE: u64 = 1 << 26;
+
+/// A snapshot log which spills its least recently used diffs to disk to stay within a memory
+/// budget.
+#[derive(Debug)]
+pub struct TieredLog<V, D = Diff<V>> {
+    base: State<V>,
+    state: State<V>,
+    cache: DiffCache<D>,
+    slots: Vec<Slot<D>>,
+    branching: usize,
+    // the directory of the caller, until the first segment is created in a subdirectory of its own
+    dir: PathBuf,
+    segments: Vec<Segment>,
+    budget: usize,
+    // the heap size of the diffs in memory
+    resident: usize,
+    // the resident diffs by when they were last used
+    lru: BTreeMap<u64, usize>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+// an entry of the log, which is in memory, on disk or both
+#[derive(Debug)]
+struct Slot<D> {
+    diff: Option<D>,
+    // when it was last used, which is its key in the lru order while it is resident
+    used: u64,
+    // the heap size it was accounted with while it is resident
+    size: usize,
+    location: Option<Location>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Location {
+    segment: usize,
+    offset: u64,
+}
+
+#[derive(Debug)]
+struct Segment {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl<V: Value + Encode, D: DiffEntries<V>> TieredLog<V, D> {
+    /// Create an empty log which starts from the provided base state and keeps at most about
+    /// `budget` bytes of diffs in memory, spilling the rest to segment files in a subdirectory of
+    /// `dir` which is created when the first diff is spilled. Any number of logs may share `dir`.
+    ///
+    /// The budget only covers the diffs of the log: the base state, the current state and the
+    /// cache needed to keep appending are always in memory.
+    pub fn create<P: AsRef<Path>>(dir: P, base: State<V>, budget: usize) -> std::io::Result<Self> {
+        Self::create_with_branching(dir, base, 2, budget)
+    }
+
+    /// Like [`TieredLog::create`], with the given branching factor (at least 2).
+    pub fn create_with_branching<P: AsRef<Path>>(
+        dir: P,
+        base: State<V>,
+        branching: usize,
+        budget: usize,
+    ) -> std::io::Result<Self> {
+        assert!(branching >= 2, "the branching factor must be at least 2");
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            state: base.clone(),
+            base,
+            cache: vec![D::default()],
+            slots: Vec::new(),
+            branching,
+            dir: dir.as_ref().to_path_buf(),
+            segments: Vec::new(),
+            budget,
+            resident: 0,
+            lru: BTreeMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    /// Apply the diff to the current state and record it as the next entry of the log.
+    ///
+    /// Building the entry may need some of the most recent entries, which are read back if they
+    /// were spilled; the error is from doing so, or from spilling entries afterwards.
+    pub fn append(&mut self, diff: D) -> Result<(), FormatError> {
+        let len = self.slots.len();
+        let k = self.branching;
+
+        // the entries since the lowest digit last wrapped around, if they are needed
+        if needs_recent(len, k) {
+            for index in crate::clear_low(len, 1, k) + 1..=len {
+                self.load(index)?;
+            }
+        }
+
+        diff.apply(&mut self.state);
+        let slots = &self.slots;
+        let entry = next_entry(&mut self.cache, diff, len, k, |ancestor| {
+            let mut entry = D::default();
+            for index in descent(ancestor, len, k) {
+                entry.union(slots[index - 1].diff.as_ref().unwrap());
+            }
+            entry
+        });
+
+        self.slots.push(Slot {
+            diff: None,
+            used: 0,
+            size: 0,
+            location: None,
+        });
+        self.insert(len, entry);
+        self.enforce_budget()?;
+        Ok(())
+    }
+
+    /// Recover the state at `index` by applying diffs onto the base state, reading back any which
+    /// were spilled.
+    pub fn recover(&mut self, index: usize) -> Result<State<V>, FormatError> {
+        assert!(
+            index <= self.len(),
+            "index {} is past the end of the log",
+            index
+        );
+        let mut state = self.base.clone();
+        for index in descent(0, index, self.branching) {
+            self.load(index)?;
+            self.slots[index - 1]
+                .diff
+                .as_ref()
+                .unwrap()
+                .apply(&mut state);
+        }
+        self.enforce_budget()?;
+        Ok(state)
+    }
+
+    // make sure the diff at `index` (1-indexed) is in memory and mark it as used; this never
+    // evicts, so that everything loaded for one operation stays available until it is done
+    fn load(&mut self, index: usize) -> Result<(), FormatError> {
+        let slot = &mut self.slots[index - 1];
+        if slot.diff.is_some() {
+            self.hits += 1;
+            self.lru.remove(&slot.used);
+            slot.used = self.clock;
+            self.lru.insert(self.clock, index);
+            self.clock += 1;
+            return Ok(());
+        }
+
+        self.misses += 1;
+        let location = slot
+            .location
+            .expect("a diff is either in memory or on disk");
+        let segment = &mut self.segments[location.segment];
+        segment.file.seek(SeekFrom::Start(location.offset))?;
+        let mut reader = BufReader::new(&mut segment.file);
+        let mut diff: D = read_diff(&mut reader, self.base.len(), &mut vec![0; V::SIZE])?;
+        // in the same form as when it was appended, so that it takes up as much of the budget
+        diff.compact();
+        self.insert(index - 1, diff);
+        Ok(())
+    }
+
+    // hold the diff of this slot in memory as the most recently used
+    fn insert(&mut self, slot: usize, diff: D) {
+        let size = diff.heap_size();
+        let entry = &mut self.slots[slot];
+        entry.diff = Some(diff);
+        entry.used = self.clock;
+        entry.size = size;
+        self.lru.insert(self.clock, slot + 1);
+        self.clock += 1;
+        self.resident += size;
+    }
+
+    // drop the least recently used diffs until the rest fit in the budget, writing out the ones
+    // which aren't on disk yet
+    fn enforce_budget(&mut self) -> std::io::Result<()> {
+        while self.resident > self.budget {
+            let Some((&used, &index)) = self.lru.first_key_value() else {
+                break;
+            };
+            // if writing fails, the diff simply stays in memory
+            if self.slots[index - 1].location.is_none() {
+                let location = self.spill(index)?;
+                self.slots[index - 1].location = Some(location);
+            }
+            self.lru.remove(&used);
+            let slot = &mut self.slots[index - 1];
+            slot.diff = None;
+            self.resident -= slot.size;
+        }
+        Ok(())
+    }
+
+    // write the diff at `index` to the end of the current segment
+    fn spill(&mut self, index: usize) -> std::io::Result<Location> {
+        if self
+            .segments
+            .last()
+            .is_none_or(|segment| segment.len >= SEGMENT_SIZE)
+        {
+            if self.segments.is_empty() {
+                self.dir = unique_dir(&self.dir)?;
+            }
+            let path = self.dir.join(format!("{}.segment", self.segments.len()));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            self.segments.push(Segment { path, file, len: 0 });
+        }
+
+        let mut payload = Vec::new();
+        write_diff(
+            &mut payload,
+            self.slots[index - 1].diff.as_ref().unwrap(),
+            &mut vec![0; V::SIZE],
+        )?;
+
+        let segment = self.segments.len() - 1;
+        let current = &mut self.segments[segment];
+        current.file.seek(SeekFrom::Start(current.len))?;
+        let mut writer = BufWriter::new(&mut current.file);
+        writer.write_all(&payload)?;
+        writer.flush()?;
+        drop(writer);
+
+        let location = Location {
+            segment,
+            offset: current.len,
+        };
+        current.len += payload.len() as u64;
+        Ok(location)
+    }
+}
+
+impl<V, D> TieredLog<V, D> {
+    /// The number of entries in the log.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Whether anything was appended to the log.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The state the log started from.
+    pub fn base(&self) -> &State<V> {
+        &self.base
+    }
+
+    /// The state after every diff in the log was applied.
+    pub fn current(&self) -> &State<V> {
+        &self.state
+    }
+
+    /// The branching factor of the skip structure.
+    pub fn branching(&self) -> usize {
+        self.branching
+    }
+
+    /// The memory budget for diffs, in bytes.
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// The heap size of the diffs currently held in memory, in bytes.
+    pub fn resident_bytes(&self) -> usize {
+        self.resident
+    }
+
+    /// The number of entries of the log which are currently held in memory.
+    pub fn resident_len(&self) -> usize {
+        self.lru.len()
+    }
+
+    /// How many times a diff which was needed was already in memory.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// How many times a diff which was needed had to be read back from disk.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+impl<V, D> Drop for TieredLog<V, D> {
+    fn drop(&mut self) {
+        // best effort: nobody else can read the segments anyway
+        for segment in &self.segments {
+            let _ = fs::remove_file(&segment.path);
+        }
+        if !self.segments.is_empty() {
+            let _ = fs::remove_dir(&self.dir);
+        }
+    }
+}
+
+// create a directory for the segments of a new log, so that logs sharing a directory never touch
+// each other's files
+fn unique_dir(parent: &Path) -> std::io::Result<PathBuf> {
+    static LOGS: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let dir = parent.join(format!(
+            "tiered-{}-{}",
+            std::process::id(),
+            LOGS.fetch_add(1, Ordering::Relaxed)
+        ));
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            // left behind by an earlier process with the same id
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::diff::squash;
+use rapid_snapshot::{Diff, DiffBackend, DiffEntries, DiffError, SortedDiff};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 16;
+
+// a state and a chain of diffs which each follow the previous one from it
+fn random_chain(rng: &mut ChaChaRng, len: usize) -> (Vec<u32>, Vec<Diff<u32>>) {
+    let start: Vec<u32> = (0..STATE_SIZE).map(|_| rng.gen_range(0..4)).collect();
+    let mut state = start.clone();
+    let mut chain = Vec::new();
+    for _ in 0..len {
+        let mut diff = random_diff(rng, &state, 8);
+        diff.retain_changes();
+        diff.apply(&mut state);
+        chain.push(diff);
+    }
+    (start, chain)
+}
+
+// the state after applying every diff in turn
+fn apply_all(start: &[u32], chain: &[Diff<u32>]) -> Vec<u32> {
+    let mut state = start.to_vec();
+    for diff in chain {
+        diff.apply(&mut state);
+    }
+    state
+}
+
+#[test]
+fn empty_diff_is_identity() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    for _ in 0..1000 {
+        let (_, chain) = random_chain(&mut rng, 1);
+        let diff = &chain[0];
+        assert_eq!(&diff.compose(&Diff::new()), diff);
+        assert_eq!(&Diff::new().compose(diff), diff);
+        assert_eq!(&squash(&chain), diff);
+    }
+}
+
+#[test]
+fn inverse_undoes_diff() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    for _ in 0..1000 {
+        let (start, chain) = random_chain(&mut rng, 1);
+        let diff = &chain[0];
+        let inverse = diff.invert();
+        assert_eq!(&inverse.invert(), diff);
+        assert!(diff.compose(&inverse).is_empty());
+        assert!(inverse.compose(diff).is_empty());
+
+        let mut state = start.clone();
+        diff.apply(&mut state);
+        inverse.apply(&mut state);
+        assert_eq!(state, start);
+    }
+}
+
+#[test]
+fn composition_is_associative() {
+    let mut rng = ChaChaRng::seed_from_u64(2);
+    for _ in 0..1000 {
+        let (start, chain) = random_chain(&mut rng, 3);
+        let (a, b, c) = (&chain[0], &chain[1], &chain[2]);
+        let left = a.compose(b).compose(c);
+        assert_eq!(left, a.compose(&b.compose(c)));
+        assert_eq!(left, squash(&chain));
+        assert_eq!(apply_all(&start, &[left]), apply_all(&start, &chain));
+    }
+
+    // the same laws hold for every representation which keeps both values
+    for _ in 0..1000 {
+        let (start, chain) = random_chain(&mut rng, 3);
+        let sorted: Vec<SortedDiff<u32>> =
+            chain.iter().map(|diff| diff.entries().collect()).collect();
+        let (a, b, c) = (&sorted[0], &sorted[1], &sorted[2]);
+        let left = a.compose(b).compose(c);
+        assert_eq!(left, a.compose(&b.compose(c)));
+        assert!(left.compose(&left.invert()).is_empty());
+
+        let mut state = start.clone();
+        left.apply(&mut state);
+        assert_eq!(state, apply_all(&start, &chain));
+    }
+}
+
+// the cell a conflict is about
+fn conflict_index(conflict: &DiffError<u32>) -> usize {
+    match *conflict {
+        DiffError::Mismatch { index, .. } | DiffError::OutOfBounds { index, .. } => index,
+    }
+}
+
+#[test]
+fn checked_apply_reports_every_conflict() {
+    let mut rng = ChaChaRng::seed_from_u64(3);
+    for _ in 0..1000 {
+        let (start, chain) = random_chain(&mut rng, 1);
+        let diff = &chain[0];
+
+        // disturb some of the cells the diff expects
+        let mut state = start.clone();
+        let mut expected = Vec::new();
+        for (index, orig, _) in diff.entries() {
+            if rng.gen() {
+                state[index] = orig + 4;
+                expected.push(DiffError::Mismatch {
+                    index,
+                    expected: orig,
+                    found: orig + 4,
+                });
+            }
+        }
+        let before = state.clone();
+
+        match diff.apply_checked(&mut state) {
+            Ok(()) => {
+                assert!(expected.is_empty());
+                assert_eq!(state, apply_all(&start, &chain));
+            }
+            Err(mut conflicts) => {
+                conflicts.sort_unstable_by_key(conflict_index);
+                expected.sort_unstable_by_key(conflict_index);
+                assert_eq!(conflicts, expected);
+                assert_eq!(state, before);
+            }
+        }
+    }
+
+    let diff: Diff<u32> = [(STATE_SIZE, (0, 1))].into_iter().collect();
+    assert_eq!(
+        diff.apply_checked(&mut [0; STATE_SIZE]),
+        Err(vec![DiffError::OutOfBounds {
+            index: STATE_SIZE,
+            len: STATE_SIZE
+        }])
+    );
+}
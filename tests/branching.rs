@@ -0,0 +1,78 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{rebuild_cache, Recovery, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+const BRANCHING: [usize; 6] = [2, 3, 4, 5, 7, 10];
+
+#[test]
+fn every_state_is_recovered() {
+    for branching in BRANCHING {
+        let mut rng = ChaChaRng::seed_from_u64(branching as u64);
+        let mut log = SnapshotLog::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+        let mut states = vec![log.current().clone()];
+
+        for _ in 0..400 {
+            let diff = random_diff(&mut rng, log.current(), 6);
+            log.append(diff);
+            states.push(log.current().clone());
+            assert_eq!(
+                rebuild_cache(log.diffs(), branching),
+                log.cache(),
+                "branching {}, len {}",
+                branching,
+                log.len()
+            );
+        }
+
+        for (index, state) in states.iter().enumerate() {
+            for recovery in [Recovery::Forward, Recovery::Backward, Recovery::Auto] {
+                assert_eq!(
+                    &log.recover_with(index, recovery),
+                    state,
+                    "branching {}, index {}, {:?}",
+                    branching,
+                    index,
+                    recovery
+                );
+            }
+        }
+
+        let mut cursor = log.cursor(0);
+        for _ in 0..200 {
+            let index = rng.gen_range(0..=log.len());
+            cursor.seek(index);
+            assert_eq!(cursor.state(), &states[index]);
+        }
+    }
+}
+
+#[test]
+fn appends_after_rebuild_match_original() {
+    for branching in BRANCHING {
+        let mut rng = ChaChaRng::seed_from_u64(branching as u64);
+        let mut original = SnapshotLog::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+        for _ in 0..150 {
+            let diff = random_diff(&mut rng, original.current(), 6);
+            original.append(diff);
+        }
+
+        let mut rebuilt = SnapshotLog::from_parts(
+            original.base().clone(),
+            original.diffs().to_vec(),
+            branching,
+        );
+        for _ in 0..150 {
+            let diff = random_diff(&mut rng, original.current(), 6);
+            original.append(diff.clone());
+            rebuilt.append(diff);
+        }
+
+        assert_eq!(rebuilt.diffs(), original.diffs(), "branching {}", branching);
+        assert_eq!(rebuilt.cache(), original.cache(), "branching {}", branching);
+    }
+}
@@ -0,0 +1,84 @@
+use rapid_snapshot::{Diff, DiffEntries, DiffError, SnapshotLog};
+
+const STATE_SIZE: usize = 8;
+
+fn diff(entries: &[(usize, u32, u32)]) -> Diff<u32> {
+    entries
+        .iter()
+        .map(|&(index, orig, new)| (index, (orig, new)))
+        .collect()
+}
+
+#[test]
+fn try_append_rejects_bad_diffs() {
+    let mut log = SnapshotLog::<u32>::new(STATE_SIZE);
+    log.try_append(diff(&[(1, 0, 5), (2, 0, 6)])).unwrap();
+    let (state, diffs, cache) = (
+        log.current().clone(),
+        log.diffs().to_vec(),
+        log.cache().to_vec(),
+    );
+
+    // cell 1 holds 5 now, not 0; the valid write to cell 3 must not happen either
+    assert_eq!(
+        log.try_append(diff(&[(3, 0, 7), (1, 0, 9)])),
+        Err(DiffError::Mismatch {
+            index: 1,
+            expected: 0,
+            found: 5
+        })
+    );
+    assert_eq!(
+        log.try_append(diff(&[(STATE_SIZE, 0, 1)])),
+        Err(DiffError::OutOfBounds {
+            index: STATE_SIZE,
+            len: STATE_SIZE
+        })
+    );
+    assert_eq!(log.current(), &state);
+    assert_eq!(log.diffs(), diffs);
+    assert_eq!(log.cache(), cache);
+    assert_eq!(log.len(), 1);
+
+    log.try_append(diff(&[(1, 5, 9)])).unwrap();
+    assert_eq!(log.recover(2)[1], 9);
+}
+
+#[test]
+fn try_union_rejects_diffs_which_do_not_follow() {
+    let mut first = diff(&[(1, 0, 5)]);
+    let expected = first.clone();
+    assert_eq!(
+        first.try_union(&diff(&[(2, 0, 1), (1, 4, 6)])),
+        Err(DiffError::Mismatch {
+            index: 1,
+            expected: 4,
+            found: 5
+        })
+    );
+    assert_eq!(first, expected);
+
+    first.try_union(&diff(&[(2, 0, 1), (1, 5, 6)])).unwrap();
+    assert_eq!(first, diff(&[(1, 0, 6), (2, 0, 1)]));
+}
+
+#[test]
+fn try_apply_leaves_state_untouched_on_error() {
+    let mut state = vec![0u32; STATE_SIZE];
+    assert_eq!(
+        diff(&[(0, 0, 1), (STATE_SIZE + 2, 0, 1)]).try_apply(&mut state),
+        Err(DiffError::OutOfBounds {
+            index: STATE_SIZE + 2,
+            len: STATE_SIZE
+        })
+    );
+    assert_eq!(
+        diff(&[(0, 0, 1), (4, 3, 1)]).try_apply(&mut state),
+        Err(DiffError::Mismatch {
+            index: 4,
+            expected: 3,
+            found: 0
+        })
+    );
+    assert_eq!(state, vec![0; STATE_SIZE]);
+}
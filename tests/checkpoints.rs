@@ -0,0 +1,169 @@
+use std::cell::Cell;
+
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{CheckpointPolicy, Diff, DiffBackend, Recovery, Reversible, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn recovery_from_checkpoints_matches() {
+    for branching in [2, 3] {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut plain = SnapshotLog::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+        let mut checkpointed = plain.clone();
+        checkpointed.set_checkpoint_policy(CheckpointPolicy {
+            every: Some(branching.pow(3)),
+            max_fraction: Some(0.5),
+        });
+
+        for _ in 0..300 {
+            let diff = random_diff(&mut rng, plain.current(), 6);
+            plain.append(diff.clone());
+            checkpointed.append(diff);
+        }
+
+        let checkpoints: Vec<_> = checkpointed.checkpoints().collect();
+        for multiple in (branching.pow(3)..=300).step_by(branching.pow(3)) {
+            assert!(checkpoints.contains(&multiple));
+        }
+        // the diffs spanning many appends touch most of the state, so they were checkpointed too
+        for (i, diff) in checkpointed.diffs().iter().enumerate() {
+            if diff.len() > STATE_SIZE / 2 {
+                assert!(checkpoints.contains(&(i + 1)));
+            }
+        }
+
+        for index in 0..=plain.len() {
+            let expected = plain.recover(index);
+            assert_eq!(checkpointed.recover_forward(index), expected);
+            for recovery in [Recovery::Forward, Recovery::Backward, Recovery::Auto] {
+                assert_eq!(checkpointed.recover_with(index, recovery), expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn truncate_drops_later_checkpoints() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut log = SnapshotLog::<u32>::new(STATE_SIZE);
+    log.set_checkpoint_policy(CheckpointPolicy {
+        every: Some(16),
+        max_fraction: None,
+    });
+    for _ in 0..100 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+    }
+    let expected = log.recover(40);
+
+    log.truncate(40);
+    assert_eq!(log.checkpoints().collect::<Vec<_>>(), [16, 32]);
+    assert_eq!(log.current(), &expected);
+
+    for _ in 0..40 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+    }
+    assert_eq!(log.checkpoints().collect::<Vec<_>>(), [16, 32, 48, 64, 80]);
+}
+
+thread_local! {
+    // the number of diffs applied or reverted on this thread
+    static APPLIED: Cell<usize> = const { Cell::new(0) };
+}
+
+// a diff which counts how often it is applied or reverted
+#[derive(Clone, Debug, Default)]
+struct Counting(Diff<u32>);
+
+impl DiffBackend<u32> for Counting {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn record(&mut self, index: usize, orig: u32, new: u32) {
+        self.0.record(index, orig, new);
+    }
+
+    fn retain_changes(&mut self) {
+        self.0.retain_changes();
+    }
+
+    fn apply(&self, state: &mut [u32]) {
+        APPLIED.with(|applied| applied.set(applied.get() + 1));
+        self.0.apply(state);
+    }
+
+    fn union(&mut self, src: &Self) {
+        self.0.union(&src.0);
+    }
+
+    fn heap_size(&self) -> usize {
+        self.0.heap_size()
+    }
+}
+
+impl Reversible<u32> for Counting {
+    fn revert(&self, state: &mut [u32]) {
+        APPLIED.with(|applied| applied.set(applied.get() + 1));
+        self.0.revert(state);
+    }
+}
+
+// recover the state, returning it with the number of diffs applied or reverted to do so
+fn counted(log: &SnapshotLog<u32, Counting>, index: usize) -> (Vec<u32>, usize) {
+    APPLIED.with(|applied| applied.set(0));
+    let state = log.recover_with(index, Recovery::Forward);
+    (state, APPLIED.with(Cell::get))
+}
+
+#[test]
+fn recovery_starts_from_checkpoints_off_the_path() {
+    let mut rng = ChaChaRng::seed_from_u64(2);
+    let mut plain = SnapshotLog::<u32, Counting>::new(STATE_SIZE);
+    let mut checkpointed = plain.clone();
+    // not a power of the branching factor, so most checkpoints are on no path from the base
+    checkpointed.set_checkpoint_policy(CheckpointPolicy {
+        every: Some(10),
+        max_fraction: None,
+    });
+    for _ in 0..300 {
+        let diff = Counting(random_diff(&mut rng, plain.current(), 6));
+        plain.append(diff.clone());
+        checkpointed.append(diff);
+    }
+
+    let (mut with, mut without) = (0, 0);
+    for index in 0..=plain.len() {
+        let (expected, plain_count) = counted(&plain, index);
+        let (state, count) = counted(&checkpointed, index);
+        assert_eq!(state, expected, "index {}", index);
+        assert!(count <= plain_count, "index {}", index);
+        if index.is_multiple_of(10) {
+            // the checkpoint itself is the state
+            assert_eq!(count, 0, "index {}", index);
+        }
+        with += count;
+        without += plain_count;
+    }
+    assert!(
+        with < without,
+        "{} diffs with checkpoints, {} without",
+        with,
+        without
+    );
+
+    // recovering backwards still starts from the current state, whatever the checkpoints
+    let backward = |log: &SnapshotLog<u32, Counting>| {
+        APPLIED.with(|applied| applied.set(0));
+        log.recover_with(plain.len() - 1, Recovery::Backward);
+        APPLIED.with(Cell::get)
+    };
+    assert_eq!(backward(&checkpointed), backward(&plain));
+}
@@ -0,0 +1,13 @@
+use rand::Rng;
+use rapid_snapshot::Diff;
+
+// a random diff of up to `max_writes` writes against the given state
+pub fn random_diff<R: Rng>(rng: &mut R, state: &[u32], max_writes: usize) -> Diff<u32> {
+    let mut diff = Diff::new();
+    for _ in 0..rng.gen_range(0..=max_writes) {
+        let idx = rng.gen_range(0..state.len());
+        // keep the values small so that writes frequently undo each other
+        diff.insert(idx, (state[idx], rng.gen_range(0..4)));
+    }
+    diff
+}
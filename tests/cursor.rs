@@ -0,0 +1,57 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::SnapshotLog;
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+fn random_log(rng: &mut ChaChaRng, len: usize) -> SnapshotLog<u32> {
+    let mut log = SnapshotLog::new(STATE_SIZE);
+    for _ in 0..len {
+        let diff = random_diff(rng, log.current(), 6);
+        log.append(diff);
+    }
+    log
+}
+
+#[test]
+fn steps_visit_every_state() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let log = random_log(&mut rng, 200);
+
+    let mut cursor = log.cursor(0);
+    assert!(!cursor.step_back());
+    assert_eq!(cursor.index(), 0);
+    for index in 1..=log.len() {
+        assert!(cursor.step_forward());
+        assert_eq!(cursor.index(), index);
+        assert_eq!(cursor.state(), &log.recover(index));
+    }
+    assert!(!cursor.step_forward());
+    assert_eq!(cursor.index(), log.len());
+
+    for index in (0..log.len()).rev() {
+        assert!(cursor.step_back());
+        assert_eq!(cursor.index(), index);
+        assert_eq!(cursor.state(), &log.recover(index));
+    }
+    assert!(!cursor.step_back());
+    assert_eq!(cursor.into_state(), *log.base());
+}
+
+#[test]
+fn seeks_reach_any_state() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let log = random_log(&mut rng, 200);
+
+    let mut cursor = log.cursor(log.len() / 2);
+    for _ in 0..500 {
+        let index = rng.gen_range(0..=log.len());
+        cursor.seek(index);
+        assert_eq!(cursor.index(), index);
+        assert_eq!(cursor.state(), &log.recover(index));
+    }
+}
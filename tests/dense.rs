@@ -0,0 +1,85 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{AdaptiveDiff, DenseDiff, DiffBackend, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 300;
+
+#[test]
+fn dense_diff_matches_entries() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let state: Vec<u32> = (0..STATE_SIZE as u32).collect();
+
+    // a full run of cells, to cover the whole word copies, with a few scattered ones around it
+    let mut entries: Vec<_> = (64..192).map(|i| (i, state[i], rng.gen())).collect();
+    entries.extend([
+        (3, state[3], 1000),
+        (250, state[250], 1001),
+        (299, state[299], 1002),
+    ]);
+    entries.sort_unstable_by_key(|&(i, _, _)| i);
+    let dense = DenseDiff::from_sorted(&entries);
+
+    assert_eq!(dense.len(), entries.len());
+    assert_eq!(dense.entries().collect::<Vec<_>>(), entries);
+    for i in 0..STATE_SIZE {
+        let expected = entries
+            .iter()
+            .find(|&&(index, _, _)| index == i)
+            .map(|&(_, orig, new)| (orig, new));
+        assert_eq!(dense.get(i), expected, "cell {}", i);
+    }
+
+    let mut applied = state.clone();
+    dense.apply(&mut applied);
+    for &(i, _, new) in &entries {
+        assert_eq!(applied[i], new);
+    }
+    dense.revert(&mut applied);
+    assert_eq!(applied, state);
+}
+
+#[test]
+fn adaptive_log_matches_map_log() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut map = SnapshotLog::<u32>::new(STATE_SIZE);
+    let mut adaptive = SnapshotLog::<u32, AdaptiveDiff<u32>>::new(STATE_SIZE);
+
+    for _ in 0..500 {
+        let diff = random_diff(&mut rng, map.current(), 40);
+        adaptive.append(AdaptiveDiff::Sparse(diff.clone()));
+        map.append(diff);
+    }
+
+    // the high levels touch most of the state, so they must have been stored densely
+    assert!(adaptive
+        .diffs()
+        .iter()
+        .any(|diff| matches!(diff, AdaptiveDiff::Dense(_))));
+    for (dense, sparse) in adaptive.diffs().iter().zip(map.diffs()) {
+        assert!(dense.storage_cost() <= sparse.storage_cost());
+    }
+    for index in 0..=map.len() {
+        assert_eq!(
+            adaptive.recover(index),
+            map.recover(index),
+            "index {}",
+            index
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "sorted by index")]
+fn dense_diff_rejects_unsorted_entries() {
+    DenseDiff::from_sorted(&[(5, 0u32, 1), (3, 0, 1)]);
+}
+
+#[test]
+#[should_panic(expected = "without duplicates")]
+fn dense_diff_rejects_duplicate_entries() {
+    DenseDiff::from_sorted(&[(3, 0u32, 1), (3, 1, 2)]);
+}
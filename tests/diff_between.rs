@@ -0,0 +1,57 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{Diff, DiffBackend, DiffEntries, SnapshotLog, SortedDiff};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+// the diff between two states, cell by cell
+fn naive_diff(from: &[u32], to: &[u32]) -> Diff<u32> {
+    (0..from.len())
+        .filter(|&index| from[index] != to[index])
+        .map(|index| (index, (from[index], to[index])))
+        .collect()
+}
+
+#[test]
+fn diff_between_matches_recovered_states() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    for _ in 0..20 {
+        let branching = rng.gen_range(2..6);
+        let mut log = SnapshotLog::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+        let mut sorted =
+            SnapshotLog::<u32, SortedDiff<u32>>::with_branching(vec![0; STATE_SIZE], branching);
+        for _ in 0..rng.gen_range(0..300) {
+            let diff = random_diff(&mut rng, log.current(), 6);
+            sorted.append(
+                diff.iter()
+                    .map(|(&i, &(orig, new))| (i, orig, new))
+                    .collect(),
+            );
+            log.append(diff);
+        }
+
+        for _ in 0..200 {
+            let from = rng.gen_range(0..=log.len());
+            let to = rng.gen_range(0..=log.len());
+            let (from_state, to_state) = (log.recover(from), log.recover(to));
+            let expected = naive_diff(&from_state, &to_state);
+
+            let diff = log.diff_between(from, to);
+            assert_eq!(diff, expected, "from {} to {}", from, to);
+            let mut state = from_state.clone();
+            diff.apply(&mut state);
+            assert_eq!(state, to_state);
+
+            let sorted = sorted.diff_between(from, to);
+            let entries: Diff<_> = sorted
+                .entries()
+                .map(|(index, orig, new)| (index, (orig, new)))
+                .collect();
+            assert_eq!(entries, expected);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::file::{MAGIC, VERSION};
+use rapid_snapshot::{FormatError, Retention, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+fn sample_log() -> SnapshotLog<u32> {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut log = SnapshotLog::from_base((0..32).collect());
+    for _ in 0..100 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+    }
+    log
+}
+
+#[test]
+fn round_trip() {
+    let log = sample_log();
+    let mut bytes = Vec::new();
+    log.save(&mut bytes).unwrap();
+
+    let loaded = SnapshotLog::<u32>::load(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.base(), log.base());
+    assert_eq!(loaded.diffs(), log.diffs());
+    assert_eq!(loaded.cache(), log.cache());
+    assert_eq!(loaded.current(), log.current());
+}
+
+#[test]
+fn round_trip_keeps_branching() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut log = SnapshotLog::with_branching((0..32).collect(), 3);
+    for _ in 0..100 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+    }
+    let mut bytes = Vec::new();
+    log.save(&mut bytes).unwrap();
+
+    let mut loaded = SnapshotLog::<u32>::load(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.branching(), 3);
+    assert_eq!(loaded.cache(), log.cache());
+    for _ in 0..50 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff.clone());
+        loaded.append(diff);
+    }
+    assert_eq!(loaded.diffs(), log.diffs());
+}
+
+#[test]
+fn round_trip_keeps_original_indices() {
+    let retention = Retention {
+        recent: 10,
+        every: Some(20),
+    };
+    let mut log = sample_log();
+    log.prune(retention);
+    let mut bytes = Vec::new();
+    log.save(&mut bytes).unwrap();
+
+    let mut loaded = SnapshotLog::<u32>::load(bytes.as_slice()).unwrap();
+    for index in 0..=log.len() {
+        assert_eq!(loaded.origin(index), log.origin(index));
+    }
+    // so the thinned states are still kept when the loaded log is pruned again
+    let kept = loaded.prune(retention);
+    assert_eq!(kept, (0..=log.len()).collect::<Vec<_>>());
+
+    // the original indices follow the base state, and must increase
+    let origins = 8 + 4 + 4 + 4 + 4 * 8 + 32 * 4;
+    assert_eq!(&bytes[origins + 8..origins + 16], &20u64.to_le_bytes());
+    bytes[origins + 8] = 0;
+    assert!(matches!(
+        SnapshotLog::<u32>::load(bytes.as_slice()),
+        Err(FormatError::Corrupt(_))
+    ));
+}
+
+#[test]
+fn rejects_truncated() {
+    let mut bytes = Vec::new();
+    sample_log().save(&mut bytes).unwrap();
+
+    for len in [0, 4, 12, 40, bytes.len() / 2, bytes.len() - 1] {
+        assert!(matches!(
+            SnapshotLog::<u32>::load(&bytes[..len]),
+            Err(FormatError::Truncated)
+        ));
+    }
+}
+
+#[test]
+fn rejects_foreign() {
+    let mut bytes = Vec::new();
+    sample_log().save(&mut bytes).unwrap();
+
+    let mut foreign = bytes.clone();
+    foreign[..8].copy_from_slice(b"\x7fELF\x02\x01\x01\x00");
+    assert!(matches!(
+        SnapshotLog::<u32>::load(foreign.as_slice()),
+        Err(FormatError::BadMagic(_))
+    ));
+
+    let mut future = bytes.clone();
+    future[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        SnapshotLog::<u32>::load(future.as_slice()),
+        Err(FormatError::UnsupportedVersion(_))
+    ));
+
+    assert!(matches!(
+        SnapshotLog::<u64>::load(bytes.as_slice()),
+        Err(FormatError::ValueSize {
+            expected: 8,
+            found: 4
+        })
+    ));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(matches!(
+        SnapshotLog::<u32>::load(trailing.as_slice()),
+        Err(FormatError::Corrupt(_))
+    ));
+
+    assert_eq!(&bytes[..8], &MAGIC);
+}
+
+#[test]
+fn rejects_inconsistent() {
+    let mut log = SnapshotLog::<u32>::from_base((0..32).collect());
+    log.append([(3, (3, 100))].into_iter().collect());
+    log.append([(3, (100, 7))].into_iter().collect());
+    let mut bytes = Vec::new();
+    log.save(&mut bytes).unwrap();
+
+    // the header and base state, then the length and index before the first original value
+    let orig = 8 + 4 + 4 + 4 + 4 * 8 + 32 * 4 + 8 + 8;
+    assert_eq!(&bytes[orig..orig + 4], &3u32.to_le_bytes());
+    let mut wrong_diff = bytes.clone();
+    wrong_diff[orig] = 4;
+    assert!(matches!(
+        SnapshotLog::<u32>::load(wrong_diff.as_slice()),
+        Err(FormatError::Corrupt(_))
+    ));
+
+    // the cache ends with the new value of its only cell
+    let end = bytes.len();
+    assert_eq!(&bytes[end - 4..], &7u32.to_le_bytes());
+    let mut wrong_cache = bytes.clone();
+    wrong_cache[end - 4] = 8;
+    assert!(matches!(
+        SnapshotLog::<u32>::load(wrong_cache.as_slice()),
+        Err(FormatError::Corrupt(_))
+    ));
+}
@@ -0,0 +1,51 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{DiffBackend, ForwardDiff, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn forward_log_matches_map_log() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut map = SnapshotLog::<u32>::new(STATE_SIZE);
+    let mut forward = SnapshotLog::<u32, ForwardDiff<u32>>::new(STATE_SIZE);
+
+    for _ in 0..500 {
+        let diff = random_diff(&mut rng, map.current(), 6);
+        let mut writes = ForwardDiff::new();
+        for (&index, &(_, new)) in &diff {
+            writes.set(index, new);
+        }
+        map.append(diff);
+        forward.append(writes);
+        assert_eq!(forward.current(), map.current());
+    }
+
+    for index in 0..=map.len() {
+        assert_eq!(
+            forward.recover_forward(index),
+            map.recover(index),
+            "index {}",
+            index
+        );
+    }
+}
+
+#[test]
+fn union_keeps_writes_which_undo_each_other() {
+    let mut diff = ForwardDiff::new();
+    diff.set(0, 1u32);
+    let mut undo = ForwardDiff::new();
+    undo.set(0, 0);
+    diff.union(&undo);
+
+    // without the original value, we can't know that the cell is back where it started
+    assert_eq!(diff.len(), 1);
+    let mut state = vec![5, 5];
+    diff.apply(&mut state);
+    assert_eq!(state, [0, 5]);
+}
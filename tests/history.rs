@@ -0,0 +1,74 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{BranchId, History, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn branches_match_linear_logs() {
+    for branching in [2, 3] {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let mut history = History::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+        // the linear log each branch should be equivalent to
+        let mut logs = vec![SnapshotLog::<u32>::with_branching(
+            vec![0; STATE_SIZE],
+            branching,
+        )];
+
+        for _ in 0..100 {
+            let diff = random_diff(&mut rng, logs[0].current(), 6);
+            logs[0].append(diff.clone());
+            history.append(History::<u32>::ROOT, diff);
+        }
+
+        for _ in 0..8 {
+            // fork any branch at any index, including from branches which forked themselves
+            let parent = history
+                .branches()
+                .nth(rng.gen_range(0..logs.len()))
+                .unwrap();
+            let index = rng.gen_range(0..=history.len(parent));
+            let branch = history.fork(parent, index);
+            assert_eq!(history.fork_point(branch), Some((parent, index)));
+
+            let mut log = logs[parent_index(&history, parent)].clone();
+            log.truncate(index);
+            logs.push(log);
+
+            // then keep appending to every branch
+            for (i, branch) in history
+                .branches()
+                .collect::<Vec<_>>()
+                .into_iter()
+                .enumerate()
+            {
+                for _ in 0..rng.gen_range(0..40) {
+                    let diff = random_diff(&mut rng, logs[i].current(), 6);
+                    logs[i].append(diff.clone());
+                    history.append(branch, diff);
+                }
+            }
+        }
+
+        assert_eq!(history.branches().count(), logs.len());
+        for (branch, log) in history.branches().zip(&logs) {
+            assert_eq!(history.len(branch), log.len());
+            assert_eq!(history.current(branch), log.current());
+            for index in 0..=log.len() {
+                assert_eq!(history.recover(branch, index), log.recover(index));
+                if index > 0 {
+                    assert_eq!(history.entry(branch, index), &log.diffs()[index - 1]);
+                }
+            }
+        }
+    }
+}
+
+// the position of the branch among all the branches
+fn parent_index(history: &History<u32>, branch: BranchId) -> usize {
+    history.branches().position(|b| b == branch).unwrap()
+}
@@ -0,0 +1,212 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{FormatError, Journal, SnapshotLog, SyncPolicy};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+// a fresh path for each test, so that they may run in parallel
+fn journal_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "rapid-snapshot-{}-{}.journal",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+// write a journal of `len` appends, returning the in-memory log and the file size after each one
+fn write_journal(path: &PathBuf, len: usize, policy: SyncPolicy) -> (SnapshotLog<u32>, Vec<u64>) {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut journal = Journal::create(path, (0..STATE_SIZE as u32).collect(), policy).unwrap();
+    let mut ends = vec![fs::metadata(path).unwrap().len()];
+    for _ in 0..len {
+        let diff = random_diff(&mut rng, journal.log().current(), 6);
+        journal.append(diff).unwrap();
+        ends.push(fs::metadata(path).unwrap().len());
+    }
+    (journal.into_log(), ends)
+}
+
+fn assert_prefix(recovered: &SnapshotLog<u32>, original: &SnapshotLog<u32>) {
+    assert_eq!(recovered.base(), original.base());
+    assert_eq!(recovered.diffs(), &original.diffs()[..recovered.len()]);
+    assert_eq!(
+        recovered.current(),
+        &original.recover(recovered.len()),
+        "len {}",
+        recovered.len()
+    );
+}
+
+#[test]
+fn reopen_recovers_everything() {
+    let path = journal_path("reopen");
+    for policy in [SyncPolicy::Always, SyncPolicy::Every(7), SyncPolicy::Never] {
+        let (original, _) = write_journal(&path, 50, policy);
+        let reopened = Journal::<u32>::open(&path, policy).unwrap();
+        assert_eq!(reopened.log().len(), original.len());
+        assert_prefix(reopened.log(), &original);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn torn_writes_recover_longest_prefix() {
+    let path = journal_path("torn");
+    let (original, ends) = write_journal(&path, 40, SyncPolicy::Never);
+    let bytes = fs::read(&path).unwrap();
+
+    // simulate a crash part way through writing each record
+    for complete in 0..original.len() {
+        let (start, end) = (ends[complete], ends[complete + 1]);
+        for cut in [start + 1, start + 8, start + 12, (start + end) / 2, end - 1] {
+            fs::write(&path, &bytes[..cut as usize]).unwrap();
+
+            let journal = Journal::<u32>::open(&path, SyncPolicy::Never).unwrap();
+            assert_eq!(journal.log().len(), complete);
+            assert_prefix(journal.log(), &original);
+            assert_eq!(fs::metadata(&path).unwrap().len(), start);
+        }
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_record_is_cut_off() {
+    let path = journal_path("corrupt");
+    let (original, ends) = write_journal(&path, 20, SyncPolicy::Never);
+    let bytes = fs::read(&path).unwrap();
+
+    // a bit flip in the payload of record 12 drops it and everything after it
+    let mut corrupt = bytes.clone();
+    corrupt[ends[12] as usize + 20] ^= 1;
+    fs::write(&path, &corrupt).unwrap();
+    let journal = Journal::<u32>::open(&path, SyncPolicy::Never).unwrap();
+    assert_eq!(journal.log().len(), 12);
+    assert_prefix(journal.log(), &original);
+
+    // garbage after the last record is ignored too
+    fs::write(&path, &bytes).unwrap();
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[0xff; 30])
+        .unwrap();
+    let journal = Journal::<u32>::open(&path, SyncPolicy::Never).unwrap();
+    assert_eq!(journal.log().len(), 20);
+    assert_eq!(fs::metadata(&path).unwrap().len(), *ends.last().unwrap());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn appends_continue_after_recovery() {
+    let path = journal_path("continue");
+    let (original, ends) = write_journal(&path, 30, SyncPolicy::Always);
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..ends[17] as usize + 5]).unwrap();
+
+    // a log which never crashed, but was cut back to the same point
+    let mut expected = original.clone();
+    expected.truncate(17);
+
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut journal = Journal::<u32>::open(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(journal.log().len(), 17);
+    for _ in 0..30 {
+        let diff = random_diff(&mut rng, journal.log().current(), 6);
+        expected.append(diff.clone());
+        journal.append(diff).unwrap();
+    }
+    assert_eq!(journal.log().diffs(), expected.diffs());
+    drop(journal);
+
+    let reopened = Journal::<u32>::open(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(reopened.log().diffs(), expected.diffs());
+    assert_eq!(reopened.log().current(), expected.current());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_foreign_and_torn_header() {
+    let path = journal_path("foreign");
+    fs::write(&path, b"definitely not a snapshot journal, but long enough").unwrap();
+    assert!(matches!(
+        Journal::<u32>::open(&path, SyncPolicy::Never),
+        Err(FormatError::BadMagic(_))
+    ));
+
+    write_journal(&path, 0, SyncPolicy::Always);
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+    assert!(matches!(
+        Journal::<u32>::open(&path, SyncPolicy::Never),
+        Err(FormatError::Truncated)
+    ));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_records_which_do_not_match() {
+    let path = journal_path("mismatch");
+    let (_, ends) = write_journal(&path, 20, SyncPolicy::Never);
+    let records = fs::read(&path).unwrap()[ends[0] as usize..].to_vec();
+
+    // intact records, but written against a different base
+    let journal = Journal::<u32>::create(&path, vec![1000; STATE_SIZE], SyncPolicy::Never).unwrap();
+    drop(journal);
+    let mut other = OpenOptions::new().append(true).open(&path).unwrap();
+    other.write_all(&records).unwrap();
+    drop(other);
+    let len = fs::metadata(&path).unwrap().len();
+    assert!(matches!(
+        Journal::<u32>::open(&path, SyncPolicy::Never),
+        Err(FormatError::Corrupt(_))
+    ));
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_appends_which_do_not_match() {
+    let path = journal_path("bad-append");
+    let (original, ends) = write_journal(&path, 10, SyncPolicy::Always);
+    let mut journal = Journal::<u32>::open(&path, SyncPolicy::Always).unwrap();
+
+    let wrong = journal.log().current()[3] + 1;
+    let error = journal
+        .append([(3, (wrong, 0))].into_iter().collect())
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert_eq!(journal.log().diffs(), original.diffs());
+    assert_eq!(fs::metadata(&path).unwrap().len(), *ends.last().unwrap());
+
+    // the journal carries on, and what it acknowledged can still be opened
+    let mut rng = ChaChaRng::seed_from_u64(2);
+    let mut expected = original;
+    for _ in 0..5 {
+        let diff = random_diff(&mut rng, journal.log().current(), 6);
+        expected.append(diff.clone());
+        journal.append(diff).unwrap();
+    }
+    drop(journal);
+    let reopened = Journal::<u32>::open(&path, SyncPolicy::Always).unwrap();
+    assert_eq!(reopened.log().diffs(), expected.diffs());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "at least one append apart")]
+fn rejects_syncing_every_zero_appends() {
+    let path = journal_path("every-zero");
+    let _ = Journal::<u32>::create(&path, vec![0; STATE_SIZE], SyncPolicy::Every(0));
+}
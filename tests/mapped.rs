@@ -0,0 +1,42 @@
+use std::fs::{self, File};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{FormatError, MappedLog, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+#[test]
+fn mapped_recovery_matches_log() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut log = SnapshotLog::from_base((0..48).collect());
+    for _ in 0..300 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+    }
+
+    let path = std::env::temp_dir().join(format!("rapid-snapshot-{}.map", std::process::id()));
+    log.save_mappable(File::create(&path).unwrap()).unwrap();
+    let mapped = MappedLog::<u32>::open(&path).unwrap();
+    assert_eq!(mapped.len(), log.len());
+
+    for index in 0..=log.len() {
+        let state = log.recover(index);
+        assert_eq!(mapped.recover(index).unwrap(), state);
+        let cell = rng.gen_range(0..state.len());
+        assert_eq!(mapped.value_at(index, cell), state[cell]);
+    }
+
+    // every cut of the file is detected before any diff is read
+    let bytes = fs::read(&path).unwrap();
+    for len in [0, 20, 100, bytes.len() / 2, bytes.len() - 1] {
+        fs::write(&path, &bytes[..len]).unwrap();
+        assert!(matches!(
+            MappedLog::<u32>::open(&path),
+            Err(FormatError::Truncated | FormatError::Corrupt(_))
+        ));
+    }
+    fs::remove_file(&path).unwrap();
+}
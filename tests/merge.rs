@@ -0,0 +1,72 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{Conflict, DiffBackend, History};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn merge_matches_cellwise_three_way() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    for _ in 0..20 {
+        let branching = rng.gen_range(2..5);
+        let mut history = History::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+        let ours = History::<u32>::ROOT;
+        for _ in 0..rng.gen_range(0..60) {
+            let diff = random_diff(&mut rng, history.current(ours), 6);
+            history.append(ours, diff);
+        }
+        let fork = rng.gen_range(0..=history.len(ours));
+        let theirs = history.fork(ours, fork);
+        for branch in [ours, theirs] {
+            for _ in 0..rng.gen_range(0..60) {
+                let diff = random_diff(&mut rng, history.current(branch), 6);
+                history.append(branch, diff);
+            }
+        }
+
+        // any state before the fork will do
+        let ancestor = rng.gen_range(0..=fork);
+        let base = history.recover(ours, ancestor);
+        let (head, other) = (history.current(ours), history.current(theirs));
+        let mut expected = head.clone();
+        let mut expected_conflicts = Vec::new();
+        for index in 0..STATE_SIZE {
+            let (b, o, t) = (base[index], head[index], other[index]);
+            if o == b {
+                expected[index] = t;
+            } else if t != b && t != o {
+                expected_conflicts.push(Conflict {
+                    index,
+                    base: b,
+                    ours: o,
+                    theirs: t,
+                });
+            }
+        }
+
+        let merge = history.merge(ancestor, ours, theirs, |_| None);
+        assert_eq!(merge.conflicts, expected_conflicts);
+        let mut merged = head.clone();
+        merge.diff.apply(&mut merged);
+        assert_eq!(merged, expected);
+
+        // resolving every conflict in favour of the other side
+        let mut seen = Vec::new();
+        let merge = history.merge(ancestor, ours, theirs, |conflict| {
+            seen.push(*conflict);
+            Some(conflict.theirs)
+        });
+        seen.sort_unstable_by_key(|conflict| conflict.index);
+        assert_eq!(seen, expected_conflicts);
+        assert!(merge.conflicts.is_empty());
+        for conflict in &expected_conflicts {
+            expected[conflict.index] = conflict.theirs;
+        }
+        history.append(ours, merge.diff);
+        assert_eq!(history.current(ours), &expected);
+    }
+}
@@ -0,0 +1,81 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{Diff, DiffBackend, DiffEntries, RangeDiff, SnapshotLog};
+
+const STATE_SIZE: usize = 64;
+
+// a diff of a few random ranges written against the given state, in both representations
+fn random_ranges<R: Rng>(rng: &mut R, state: &[u32]) -> (Diff<u32>, RangeDiff<u32>) {
+    let mut map = Diff::new();
+    let mut ranges = RangeDiff::new();
+    for _ in 0..rng.gen_range(0..=3) {
+        let start = rng.gen_range(0..state.len());
+        let len = rng.gen_range(1..=(state.len() - start).min(12));
+        // keep the values small so that writes frequently undo each other
+        let new: Vec<u32> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+        let orig = &state[start..start + len];
+
+        ranges.record_range(start, orig, &new);
+        for (i, (&orig, &new)) in orig.iter().zip(&new).enumerate() {
+            map.insert(start + i, (orig, new));
+        }
+    }
+    (map, ranges)
+}
+
+fn sorted_entries<D: DiffEntries<u32>>(diff: &D) -> Vec<(usize, u32, u32)> {
+    let mut entries: Vec<_> = diff.entries().collect();
+    entries.sort_unstable();
+    entries
+}
+
+#[test]
+fn union_merges_overlapping_and_adjacent_runs() {
+    let state: Vec<u32> = (0..16).collect();
+
+    let mut diff = RangeDiff::new();
+    diff.record_range(2, &state[2..5], &[20, 30, 40]);
+    let mut later = RangeDiff::new();
+    later.record_range(4, &[40, 5], &[41, 51]);
+    later.record_range(6, &state[6..8], &[60, 70]);
+    diff.union(&later);
+
+    let runs: Vec<_> = diff.runs().collect();
+    assert_eq!(
+        runs,
+        [(2, &[2, 3, 4, 5, 6, 7][..], &[20, 30, 41, 51, 60, 70][..])]
+    );
+
+    // putting a cell back splits the run around it
+    let mut undo = RangeDiff::new();
+    undo.record_range(3, &[30, 41], &[3, 4]);
+    diff.union(&undo);
+    let runs: Vec<_> = diff.runs().map(|(start, _, new)| (start, new)).collect();
+    assert_eq!(runs, [(2, &[20][..]), (5, &[51, 60, 70][..])]);
+}
+
+#[test]
+fn range_log_matches_map_log() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut map = SnapshotLog::<u32>::new(STATE_SIZE);
+    let mut ranges = SnapshotLog::<u32, RangeDiff<u32>>::new(STATE_SIZE);
+
+    for _ in 0..500 {
+        let (map_diff, range_diff) = random_ranges(&mut rng, map.current());
+        map.append(map_diff);
+        ranges.append(range_diff);
+        assert_eq!(ranges.current(), map.current());
+    }
+
+    for (range_diff, map_diff) in ranges.diffs().iter().zip(map.diffs()) {
+        assert_eq!(sorted_entries(range_diff), sorted_entries(map_diff));
+        // runs are as long as they can be
+        let runs: Vec<_> = range_diff.runs().collect();
+        for pair in runs.windows(2) {
+            assert!(pair[0].0 + pair[0].1.len() < pair[1].0);
+        }
+    }
+    for index in 0..=map.len() {
+        assert_eq!(ranges.recover(index), map.recover(index), "index {}", index);
+    }
+}
@@ -0,0 +1,60 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{CheckpointPolicy, Recovery, Retention, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn rebased_log_matches_shifted_states() {
+    for branching in [2, 3] {
+        for index in [0, 1, 37, 128, 299, 300] {
+            let mut rng = ChaChaRng::seed_from_u64(0);
+            let mut original = SnapshotLog::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+            original.set_checkpoint_policy(CheckpointPolicy {
+                every: Some(50),
+                max_fraction: None,
+            });
+            for _ in 0..300 {
+                let diff = random_diff(&mut rng, original.current(), 6);
+                original.append(diff);
+            }
+
+            let mut rebased = original.clone();
+            rebased.rebase(index);
+            assert_eq!(rebased.len(), original.len() - index);
+            assert_eq!(rebased.base(), &original.recover(index));
+            assert_eq!(rebased.current(), original.current());
+            for i in 0..=rebased.len() {
+                let expected = original.recover(index + i);
+                for recovery in [Recovery::Forward, Recovery::Backward] {
+                    assert_eq!(rebased.recover_with(i, recovery), expected);
+                }
+            }
+            for checkpoint in rebased.checkpoints() {
+                assert!((index + checkpoint).is_multiple_of(50));
+            }
+
+            // the structure is exactly the one of a log which started from that state
+            let mut pruned = original.clone();
+            pruned.prune(Retention {
+                recent: original.len() - index,
+                every: None,
+            });
+            assert_eq!(rebased.diffs(), pruned.diffs());
+            assert_eq!(rebased.cache(), pruned.cache());
+
+            for _ in 0..100 {
+                let diff = random_diff(&mut rng, original.current(), 6);
+                original.append(diff.clone());
+                rebased.append(diff);
+            }
+            for i in 0..=rebased.len() {
+                assert_eq!(rebased.recover(i), original.recover(index + i));
+            }
+        }
+    }
+}
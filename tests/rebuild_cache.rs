@@ -0,0 +1,57 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{rebuild_cache, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn rebuilt_cache_matches_live_cache() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut log = SnapshotLog::<u32>::new(STATE_SIZE);
+
+    assert_eq!(rebuild_cache(log.diffs(), 2), log.cache());
+    for _ in 0..600 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+        assert_eq!(
+            rebuild_cache(log.diffs(), 2),
+            log.cache(),
+            "len {}",
+            log.len()
+        );
+    }
+}
+
+#[test]
+fn appends_after_rebuild_match_original() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut original = SnapshotLog::<u32>::new(STATE_SIZE);
+
+    for split in [0, 1, 2, 3, 7, 8, 63, 64, 100, 255] {
+        while original.len() < split {
+            let diff = random_diff(&mut rng, original.current(), 6);
+            original.append(diff);
+        }
+
+        let mut rebuilt =
+            SnapshotLog::from_parts(original.base().clone(), original.diffs().to_vec(), 2);
+        let mut continued = original.clone();
+        assert_eq!(rebuilt.current(), continued.current());
+
+        for _ in 0..300 {
+            let diff = random_diff(&mut rng, continued.current(), 6);
+            continued.append(diff.clone());
+            rebuilt.append(diff);
+        }
+
+        assert_eq!(rebuilt.diffs(), continued.diffs());
+        assert_eq!(rebuilt.cache(), continued.cache());
+        for index in 0..=continued.len() {
+            assert_eq!(rebuilt.recover(index), continued.recover(index));
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{Recovery, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn every_direction_recovers_the_same_states() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut log = SnapshotLog::<u32>::new(STATE_SIZE);
+    // the states as they were appended, to recover against
+    let mut states = vec![log.current().clone()];
+    for _ in 0..300 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+        states.push(log.current().clone());
+    }
+
+    for (index, expected) in states.iter().enumerate() {
+        for recovery in [Recovery::Forward, Recovery::Backward, Recovery::Auto] {
+            assert_eq!(
+                &log.recover_with(index, recovery),
+                expected,
+                "index {} {:?}",
+                index,
+                recovery
+            );
+        }
+        assert_eq!(&log.recover(index), expected);
+    }
+    assert_eq!(log.recover_with(0, Recovery::Backward), *log.base());
+    assert_eq!(
+        log.recover_with(log.len(), Recovery::Forward),
+        *log.current()
+    );
+}
@@ -0,0 +1,166 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{CheckpointPolicy, Recovery, Retention, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn pruned_log_keeps_states() {
+    for branching in [2, 3] {
+        for retention in [
+            Retention {
+                recent: 50,
+                every: Some(20),
+            },
+            Retention {
+                recent: 50,
+                every: None,
+            },
+            Retention {
+                recent: 1000,
+                every: None,
+            },
+        ] {
+            let mut rng = ChaChaRng::seed_from_u64(0);
+            let mut original = SnapshotLog::<u32>::with_branching(vec![0; STATE_SIZE], branching);
+            original.set_checkpoint_policy(CheckpointPolicy {
+                every: Some(64),
+                max_fraction: None,
+            });
+            for _ in 0..300 {
+                let diff = random_diff(&mut rng, original.current(), 6);
+                original.append(diff);
+            }
+
+            let mut pruned = original.clone();
+            let kept = pruned.prune(retention);
+            assert_eq!(pruned.len(), kept.len() - 1);
+            assert_eq!(*kept.last().unwrap(), original.len());
+            assert_eq!(pruned.current(), original.current());
+            for (index, &old) in kept.iter().enumerate() {
+                let expected = original.recover(old);
+                for recovery in [Recovery::Forward, Recovery::Backward] {
+                    assert_eq!(pruned.recover_with(index, recovery), expected);
+                }
+            }
+            for checkpoint in pruned.checkpoints() {
+                assert!(kept[checkpoint].is_multiple_of(64));
+            }
+
+            // and the pruned log carries on like the original
+            for _ in 0..100 {
+                let diff = random_diff(&mut rng, original.current(), 6);
+                original.append(diff.clone());
+                pruned.append(diff);
+            }
+            let offset = original.len() - pruned.len();
+            for index in kept.len()..=pruned.len() {
+                assert_eq!(pruned.recover(index), original.recover(index + offset));
+            }
+        }
+    }
+}
+
+#[test]
+fn prune_drops_prefix() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut log = SnapshotLog::<u32>::new(STATE_SIZE);
+    for _ in 0..100 {
+        let diff = random_diff(&mut rng, log.current(), 6);
+        log.append(diff);
+    }
+    let expected = log.recover(90);
+
+    let kept = log.prune(Retention {
+        recent: 10,
+        every: None,
+    });
+    assert_eq!(kept, (90..=100).collect::<Vec<_>>());
+    assert_eq!(log.base(), &expected);
+    assert_eq!(log.len(), 10);
+}
+
+#[test]
+fn pruning_again_keeps_thinned_states() {
+    let retention = Retention {
+        recent: 10,
+        every: Some(10),
+    };
+    let mut rng = ChaChaRng::seed_from_u64(2);
+    let mut original = SnapshotLog::<u32>::new(STATE_SIZE);
+    let mut pruned = original.clone();
+    for round in 1..=3 {
+        for _ in 0..100 {
+            let diff = random_diff(&mut rng, original.current(), 6);
+            original.append(diff.clone());
+            pruned.append(diff);
+        }
+        pruned.prune(retention);
+
+        // the states kept are the same as if the whole history was pruned at once
+        let origins: Vec<usize> = (0..=pruned.len()).map(|i| pruned.origin(i)).collect();
+        let expected: Vec<usize> = (0..round * 100 - 10)
+            .step_by(10)
+            .chain(round * 100 - 10..=round * 100)
+            .collect();
+        assert_eq!(origins, expected);
+        for (index, &origin) in origins.iter().enumerate() {
+            assert_eq!(pruned.recover(index), original.recover(origin));
+        }
+    }
+
+    // so pruning again with the same policy changes nothing
+    let before = pruned.clone();
+    let kept = pruned.prune(retention);
+    assert_eq!(kept, (0..=before.len()).collect::<Vec<_>>());
+    assert_eq!(pruned.diffs(), before.diffs());
+    assert_eq!(pruned.base(), before.base());
+
+    // and rebasing carries the original indices along
+    pruned.rebase(5);
+    for index in 0..=pruned.len() {
+        assert_eq!(pruned.origin(index), before.origin(index + 5));
+    }
+}
+
+#[test]
+fn truncating_forgets_dropped_states() {
+    let retention = Retention {
+        recent: 2,
+        every: Some(5),
+    };
+    let mut rng = ChaChaRng::seed_from_u64(3);
+    let mut original = SnapshotLog::<u32>::new(STATE_SIZE);
+    for _ in 0..20 {
+        let diff = random_diff(&mut rng, original.current(), 6);
+        original.append(diff);
+    }
+    let mut pruned = original.clone();
+    pruned.prune(retention);
+    let origins: Vec<usize> = (0..=pruned.len()).map(|i| pruned.origin(i)).collect();
+    assert_eq!(origins, [0, 5, 10, 15, 18, 19, 20]);
+
+    // the states after the truncated one are appended anew
+    pruned.truncate(1);
+    original.truncate(5);
+    for _ in 0..10 {
+        let diff = random_diff(&mut rng, original.current(), 6);
+        original.append(diff.clone());
+        pruned.append(diff);
+    }
+    for index in 0..=pruned.len() {
+        let expected = if index == 0 { 0 } else { index + 4 };
+        assert_eq!(pruned.origin(index), expected);
+    }
+
+    pruned.prune(retention);
+    let origins: Vec<usize> = (0..=pruned.len()).map(|i| pruned.origin(i)).collect();
+    assert_eq!(origins, [0, 5, 10, 13, 14, 15]);
+    for (index, &origin) in origins.iter().enumerate() {
+        assert_eq!(pruned.recover(index), original.recover(origin));
+    }
+}
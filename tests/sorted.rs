@@ -0,0 +1,69 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{Diff, DiffBackend, DiffEntries, SnapshotLog, SortedDiff};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 64;
+
+fn sorted_entries<D: DiffEntries<u32>>(diff: &D) -> Vec<(usize, u32, u32)> {
+    let mut entries: Vec<_> = diff.entries().collect();
+    entries.sort_unstable();
+    entries
+}
+
+fn to_sorted(diff: &Diff<u32>) -> SortedDiff<u32> {
+    diff.iter()
+        .map(|(&i, &(orig, new))| (i, orig, new))
+        .collect()
+}
+
+#[test]
+fn union_matches_map_union() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    for _ in 0..2000 {
+        let mut state: Vec<u32> = (0..STATE_SIZE).map(|_| rng.gen_range(0..4)).collect();
+        // as in a log, the diffs only hold cells which change
+        let mut first = random_diff(&mut rng, &state, 20);
+        first.retain_changes();
+        first.apply(&mut state);
+        let mut second = random_diff(&mut rng, &state, 20);
+        second.retain_changes();
+
+        let mut map = first.clone();
+        map.union(&second);
+        let mut sorted = to_sorted(&first);
+        sorted.union(&to_sorted(&second));
+
+        // the entries stay sorted and unique, and match the map exactly
+        let entries: Vec<_> = sorted.entries().collect();
+        assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(entries, sorted_entries(&map));
+    }
+}
+
+#[test]
+fn sorted_log_matches_map_log() {
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut map = SnapshotLog::<u32>::new(STATE_SIZE);
+    let mut sorted = SnapshotLog::<u32, SortedDiff<u32>>::new(STATE_SIZE);
+
+    for _ in 0..500 {
+        let diff = random_diff(&mut rng, map.current(), 10);
+        sorted.append(to_sorted(&diff));
+        map.append(diff);
+        assert_eq!(sorted.current(), map.current());
+    }
+
+    for (sorted_diff, map_diff) in sorted.diffs().iter().zip(map.diffs()) {
+        assert_eq!(sorted_entries(sorted_diff), sorted_entries(map_diff));
+    }
+    for (sorted_diff, map_diff) in sorted.cache().iter().zip(map.cache()) {
+        assert_eq!(sorted_entries(sorted_diff), sorted_entries(map_diff));
+    }
+    for index in 0..=map.len() {
+        assert_eq!(sorted.recover(index), map.recover(index), "index {}", index);
+    }
+}
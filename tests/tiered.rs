@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{AdaptiveDiff, DiffBackend, SnapshotLog, TieredLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+// a fresh directory for each test, so that they may run in parallel
+fn segment_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "rapid-snapshot-{}-{}.segments",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn spilled_log_matches_memory_log() {
+    let dir = segment_dir("matches");
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let base: Vec<u32> = (0..STATE_SIZE as u32).collect();
+    let mut memory = SnapshotLog::<u32>::with_branching(base.clone(), 3);
+    // small enough that only a handful of diffs fit
+    let mut tiered = TieredLog::<u32>::create_with_branching(&dir, base, 3, 1 << 10).unwrap();
+
+    for _ in 0..500 {
+        let diff = random_diff(&mut rng, memory.current(), 6);
+        memory.append(diff.clone());
+        tiered.append(diff).unwrap();
+        assert!(tiered.resident_bytes() <= tiered.budget());
+    }
+    assert_eq!(tiered.current(), memory.current());
+    assert!(tiered.resident_len() < tiered.len());
+
+    // visit the states out of order, so that diffs are read back and dropped again repeatedly
+    for _ in 0..500 {
+        let index = rng.gen_range(0..=memory.len());
+        assert_eq!(
+            tiered.recover(index).unwrap(),
+            memory.recover(index),
+            "index {}",
+            index
+        );
+    }
+    assert!(tiered.misses() > 0);
+    assert!(tiered.hits() > 0);
+
+    drop(tiered);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn large_budget_never_spills() {
+    let dir = segment_dir("resident");
+    let mut rng = ChaChaRng::seed_from_u64(1);
+    let mut tiered = TieredLog::<u32>::create(&dir, vec![0; STATE_SIZE], usize::MAX).unwrap();
+
+    for _ in 0..100 {
+        let diff = random_diff(&mut rng, tiered.current(), 6);
+        tiered.append(diff).unwrap();
+    }
+    for index in 0..=tiered.len() {
+        tiered.recover(index).unwrap();
+    }
+    assert_eq!(tiered.resident_len(), tiered.len());
+    assert_eq!(tiered.misses(), 0);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    drop(tiered);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn appends_only_read_back_entries_they_compose() {
+    let dir = segment_dir("appends");
+    let mut rng = ChaChaRng::seed_from_u64(2);
+    // hardly anything stays in memory, so almost every entry an append needs is read back
+    let mut tiered =
+        TieredLog::<u32>::create_with_branching(&dir, vec![0; STATE_SIZE], 16, 0).unwrap();
+    for _ in 0..1600 {
+        let diff = random_diff(&mut rng, tiered.current(), 6);
+        tiered.append(diff).unwrap();
+    }
+
+    // the 15 entries before every multiple of 16, except 16 itself and the multiples of 256;
+    // only the empty diffs, which take no memory, are still there
+    assert_eq!(tiered.hits() + tiered.misses(), (100 - 1 - 6) * 15);
+    assert!(tiered.hits() < tiered.misses());
+    drop(tiered);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn logs_sharing_a_directory_keep_their_own_segments() {
+    let dir = segment_dir("shared");
+    let mut rng = ChaChaRng::seed_from_u64(3);
+    let mut logs: Vec<_> = (0..2)
+        .map(|i| {
+            let base = vec![i; STATE_SIZE];
+            (
+                SnapshotLog::<u32>::from_base(base.clone()),
+                TieredLog::<u32>::create(&dir, base, 0).unwrap(),
+            )
+        })
+        .collect();
+    for _ in 0..200 {
+        for (memory, tiered) in &mut logs {
+            let diff = random_diff(&mut rng, memory.current(), 6);
+            memory.append(diff.clone());
+            tiered.append(diff).unwrap();
+        }
+    }
+
+    // dropping one log leaves the segments of the other alone
+    let (memory, mut tiered) = logs.pop().unwrap();
+    drop(logs);
+    for index in 0..=memory.len() {
+        assert_eq!(tiered.recover(index).unwrap(), memory.recover(index));
+    }
+    assert!(tiered.misses() > 0);
+
+    drop(tiered);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn read_back_diffs_keep_their_compact_form() {
+    let dir = segment_dir("compact");
+    // every diff changes every cell, so every entry is stored dense
+    let change = |state: &[u32]| -> AdaptiveDiff<u32> {
+        let mut diff = AdaptiveDiff::default();
+        for (cell, &value) in state.iter().enumerate() {
+            diff.record(cell, value, value + 1);
+        }
+        diff
+    };
+    let mut dense = change(&[0; STATE_SIZE]);
+    dense.compact();
+    // room for a single entry
+    let mut tiered =
+        TieredLog::<u32, AdaptiveDiff<u32>>::create(&dir, vec![0; STATE_SIZE], dense.heap_size())
+            .unwrap();
+    for _ in 0..8 {
+        let diff = change(tiered.current());
+        tiered.append(diff).unwrap();
+    }
+    assert_eq!(tiered.resident_len(), 1);
+
+    // entry 4 is read back and takes the place of entry 8, rather than being dropped again
+    tiered.recover(4).unwrap();
+    assert_eq!(tiered.resident_bytes(), dense.heap_size());
+    let misses = tiered.misses();
+    tiered.recover(4).unwrap();
+    assert_eq!(tiered.misses(), misses);
+
+    drop(tiered);
+    fs::remove_dir(&dir).unwrap();
+}
@@ -0,0 +1,53 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{Diff, SnapshotLog};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn truncated_log_matches_log_without_later_appends() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let diffs: Vec<Diff<u32>> = {
+        let mut log = SnapshotLog::<u32>::new(STATE_SIZE);
+        (0..200)
+            .map(|_| {
+                let diff = random_diff(&mut rng, log.current(), 6);
+                log.append(diff.clone());
+                diff
+            })
+            .collect()
+    };
+
+    for index in [0, 1, 2, 63, 64, 65, 127, 150, 200] {
+        let mut log = SnapshotLog::<u32>::new(STATE_SIZE);
+        for diff in &diffs {
+            log.append(diff.clone());
+        }
+        log.truncate(index);
+
+        // the same as a log which only ever saw the diffs up to the index
+        let mut expected = SnapshotLog::<u32>::new(STATE_SIZE);
+        for diff in &diffs[..index] {
+            expected.append(diff.clone());
+        }
+        assert_eq!(log.len(), index);
+        assert_eq!(log.current(), expected.current());
+        assert_eq!(log.diffs(), expected.diffs());
+        assert_eq!(log.cache(), expected.cache());
+
+        // and appending carries on from the truncated state
+        for _ in 0..100 {
+            let diff = random_diff(&mut rng, log.current(), 6);
+            log.append(diff.clone());
+            expected.append(diff);
+        }
+        assert_eq!(log.diffs(), expected.diffs());
+        for i in 0..=log.len() {
+            assert_eq!(log.recover(i), expected.recover(i), "index {}", i);
+        }
+    }
+}
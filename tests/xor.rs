@@ -0,0 +1,43 @@
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use rapid_snapshot::{DiffBackend, Recovery, SnapshotLog, XorDiff};
+
+mod common;
+
+use common::random_diff;
+
+const STATE_SIZE: usize = 32;
+
+#[test]
+fn xor_log_matches_map_log() {
+    let mut rng = ChaChaRng::seed_from_u64(0);
+    let mut map = SnapshotLog::<u32>::new(STATE_SIZE);
+    let mut xor = SnapshotLog::<u32, XorDiff<u32>>::new(STATE_SIZE);
+
+    for _ in 0..500 {
+        let diff = random_diff(&mut rng, map.current(), 6);
+        let mut deltas = XorDiff::new();
+        for (&index, &(orig, new)) in &diff {
+            deltas.record(index, orig, new);
+        }
+        map.append(diff);
+        xor.append(deltas);
+    }
+
+    // writes which cancel out are dropped, so every delta is a real change
+    for (deltas, diff) in xor.diffs().iter().zip(map.diffs()) {
+        assert_eq!(deltas.len(), diff.len());
+        assert!(deltas.deltas().all(|(_, &delta)| delta != 0));
+    }
+    for index in 0..=map.len() {
+        let expected = map.recover(index);
+        for recovery in [Recovery::Forward, Recovery::Backward] {
+            assert_eq!(
+                xor.recover_with(index, recovery),
+                expected,
+                "index {}",
+                index
+            );
+        }
+    }
+}